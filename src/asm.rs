@@ -0,0 +1,502 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{parse_reg, HALT};
+
+// NAMED CONSTANTS============================================================
+/* condition code suffixes, indexed by condition code */
+pub(crate) const CONDITIONS : [&str; 15] = ["eq", "ne", "cs", "cc", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le", "al"];
+
+/* data processing mnemonics, indexed by opcode */
+pub(crate) const OPCODES : [&str; 16] = ["and", "eor", "sub", "rsb", "add", "adc", "sbc", "rsc", "tst", "teq", "cmp", "cmn", "orr", "mov", "bic", "mvn"];
+
+/* shift mnemonics, indexed by shift type */
+pub(crate) const SHIFTS : [&str; 4] = ["lsl", "lsr", "asr", "ror"];
+
+/* always condition code */
+const AL : u32 = 14;
+
+/* largest offset of a single data transfer immediate */
+const MAX_TRANSFER_OFFSET : u32 = 0xFFF;
+
+/* range of a branch offset (a signed 24 bit word offset), -32MiB to +32MiB - 4 */
+const MIN_BRANCH_OFFSET : i32 = -0x200_0000;
+const MAX_BRANCH_OFFSET : i32 = 0x1FF_FFFC;
+
+// ERROR TYPES==================================================================
+/* Error produced when a line of assembly cannot be assembled */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line : usize,
+    pub message : String
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {write!(f, "line {}: {}", self.line, self.message)}
+}
+
+impl std::error::Error for AssembleError {}
+
+/* Constants loaded with ldr rd, =<expr>, placed in a literal pool after the last instruction */
+struct LiteralPool {
+    base : u32,
+    values : Vec<u32>
+}
+
+impl LiteralPool {
+    /* Get the address of a constant in the pool, adding it if it is not already present
+    value   <-  the constant */
+    fn address(&mut self, value : u32) -> u32 {
+        let ind = self.values.iter().position(|v| *v == value).unwrap_or_else(|| {
+            self.values.push(value);
+            self.values.len() - 1
+        });
+        self.base + ind as u32 * 4
+    }
+}
+
+// ASSEMBLER====================================================================
+/* Assemble a source string into the little endian byte stream accepted by load_program.
+Supports data processing, multiply, single data transfer (ldr/str/ldrb/strb), branch, bx, swi, clz, qadd/qsub/qdadd/qdsub
+and mrs/msr mnemonics with condition suffixes, the lsl and halt pseudo-instructions, labels and ';' comments.
+Constants loaded with ldr rd, =<expr> that do not fit a mov are placed in a literal pool after the last
+instruction, so the program must halt (preferably with halt) before execution reaches the pool.
+source  <-  the assembly program, one instruction per line
+return  <-  the binary, or the first error encountered */
+pub fn assemble(source : &str) -> Result<Vec<u8>, AssembleError> {
+    /* first pass: strip comments and assign addresses to labels and instructions */
+    let mut labels = HashMap::new();
+    let mut instructions = Vec::new();
+
+    for (ind, raw) in source.lines().enumerate() {
+        let mut text = raw.split([';', '@']).next().unwrap().trim();
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if label.is_empty() || !label.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(AssembleError {line : ind + 1, message : format!("Invalid label: {}", label)});
+            }
+            labels.insert(label.to_string(), instructions.len() as u32 * 4);
+            text = text[colon + 1..].trim();
+        }
+        if !text.is_empty() {instructions.push((ind + 1, text));}
+    }
+
+    /* second pass: encode each instruction, then append the literal pool */
+    let mut pool = LiteralPool {base : instructions.len() as u32 * 4, values : Vec::new()};
+    let mut binary = Vec::with_capacity(instructions.len() * 4);
+    for (ind, (line, text)) in instructions.iter().enumerate() {
+        let word = encode_line(text, ind as u32 * 4, &labels, &mut pool).map_err(|message| AssembleError {line : *line, message})?;
+        binary.extend_from_slice(&word.to_le_bytes());
+    }
+    for value in pool.values {binary.extend_from_slice(&value.to_le_bytes())}
+    Ok(binary)
+}
+
+/* Encode a single instruction
+text    <-  the instruction with comments and labels removed
+addr    <-  the address the instruction will be loaded at
+labels  <-  addresses of all labels in the program
+pool    <-  literal pool for ldr rd, =<expr> constants */
+fn encode_line(text : &str, addr : u32, labels : &HashMap<String, u32>, pool : &mut LiteralPool) -> Result<u32, String> {
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(ind) => (text[..ind].to_lowercase(), text[ind..].trim()),
+        None => (text.to_lowercase(), "")
+    };
+    let operands = split_operands(rest);
+
+    /* branches: b{l}{cond} */
+    if let Some(suffix) = mnemonic.strip_prefix('b') {
+        let branch = match parse_cond(suffix) {
+            Some(cond) => Some((false, cond)),
+            None => suffix.strip_prefix('l').and_then(parse_cond).map(|cond| (true, cond))
+        };
+        if let Some((link, cond)) = branch {
+            expect_operands(&operands, 1)?;
+            let target = match labels.get(operands[0]) {
+                Some(target) => *target,
+                None => parse_number(operands[0]).ok_or(format!("Undefined label: {}", operands[0]))?
+            };
+            if !target.is_multiple_of(4) {return Err(format!("Branch target {:#x} is not word aligned", target))}
+            let offset = target.wrapping_sub(addr + 8) as i32;
+            if !(MIN_BRANCH_OFFSET..=MAX_BRANCH_OFFSET).contains(&offset) {
+                return Err(format!("Branch target {:#x} is out of range ({:#x} bytes away)", target, offset))
+            }
+            return Ok(encode_branch(cond, link, offset));
+        }
+    }
+
+    /* halt pseudo-instruction, assembled as swi 1 */
+    if mnemonic == "halt" {
+        expect_operands(&operands, 0)?;
+        return Ok(HALT);
+    }
+
+    /* software interrupt: swi{cond} */
+    if let Some(cond) = mnemonic.strip_prefix("swi").and_then(parse_cond) {
+        expect_operands(&operands, 1)?;
+        let comment = parse_number(operands[0].trim_start_matches('#')).filter(|c| *c <= 0xFF_FFFF).ok_or(format!("Invalid software interrupt: {}", operands[0]))?;
+        return Ok(encode_swi(cond, comment));
+    }
+
+    /* status register transfer: mrs{cond} rd, cpsr and msr{cond} cpsr{_fields}, <rm|#expr> */
+    if let Some(cond) = mnemonic.strip_prefix("mrs").and_then(parse_cond) {
+        expect_operands(&operands, 2)?;
+        if !operands[1].eq_ignore_ascii_case("cpsr") {return Err(format!("Invalid status register: {}", operands[1]))}
+        return Ok(cond << 28 | 0x010F_0000 | parse_register(operands[0])? << 12);
+    }
+    if let Some(cond) = mnemonic.strip_prefix("msr").and_then(parse_cond) {
+        expect_operands(&operands, 2)?;
+        let fields = match operands[0].to_lowercase().as_str() {
+            "cpsr" => 0b1001,
+            "cpsr_flg" | "cpsr_f" => 0b1000,
+            "cpsr_all" | "cpsr_fc" | "cpsr_cf" => 0b1001,
+            "cpsr_c" => 0b0001,
+            _ => return Err(format!("Invalid status register: {}", operands[0]))
+        };
+        let source = encode_operand2(&operands[1..])?;
+        if source & 0xFF0 != 0 && source & 1 << 25 == 0 {return Err(format!("Invalid operand: {}", operands[1]))}
+        return Ok(cond << 28 | 0x0120_F000 | fields << 16 | source);
+    }
+
+    /* branch and exchange: bx{cond} rm */
+    if let Some(cond) = mnemonic.strip_prefix("bx").and_then(parse_cond) {
+        expect_operands(&operands, 1)?;
+        return Ok(cond << 28 | 0x012F_FF10 | parse_register(operands[0])?);
+    }
+
+    /* saturating arithmetic: qadd/qsub/qdadd/qdsub{cond} rd, rm, rn */
+    for (op, base) in ["qadd", "qsub", "qdadd", "qdsub"].iter().enumerate() {
+        if let Some(cond) = mnemonic.strip_prefix(base).and_then(parse_cond) {
+            expect_operands(&operands, 3)?;
+            let (rd, rm, rn) = (parse_register(operands[0])?, parse_register(operands[1])?, parse_register(operands[2])?);
+            return Ok(cond << 28 | 0x0100_0050 | (op as u32) << 21 | rn << 16 | rd << 12 | rm);
+        }
+    }
+
+    /* count leading zeros: clz{cond} rd, rm */
+    if let Some(cond) = mnemonic.strip_prefix("clz").and_then(parse_cond) {
+        expect_operands(&operands, 2)?;
+        return Ok(cond << 28 | 0x016F_0F10 | parse_register(operands[0])? << 12 | parse_register(operands[1])?);
+    }
+
+    /* multiply: mul/mla{cond}{s} */
+    for (base, accumulate) in [("mul", false), ("mla", true)].iter() {
+        if let Some((cond, s)) = mnemonic.strip_prefix(base).and_then(|suffix| parse_suffix(suffix, "s")) {
+            expect_operands(&operands, if *accumulate {4} else {3})?;
+            let rd = parse_register(operands[0])?;
+            let rm = parse_register(operands[1])?;
+            let rs = parse_register(operands[2])?;
+            let rn = if *accumulate {parse_register(operands[3])?} else {0};
+            return Ok(encode_multiply(cond, *accumulate, s, rd, rn, rs, rm));
+        }
+    }
+
+    /* single data transfer: ldr/str{cond}{b} */
+    for (base, load) in [("ldr", true), ("str", false)].iter() {
+        if let Some((cond, byte)) = mnemonic.strip_prefix(base).and_then(|suffix| parse_suffix(suffix, "b")) {
+            return encode_transfer(cond, *load, byte, &operands, addr, pool);
+        }
+    }
+
+    /* lsl pseudo-instruction: lsl{cond} rn, <#expr> -> mov rn, rn, lsl <#expr> */
+    if let Some((cond, s)) = mnemonic.strip_prefix("lsl").and_then(|suffix| parse_suffix(suffix, "s")) {
+        expect_operands(&operands, 2)?;
+        let rd = parse_register(operands[0])?;
+        let shift = format!("{}, lsl {}", operands[0], operands[1]);
+        return Ok(encode_data_processing(cond, 13, s, 0, rd, encode_operand2(&split_operands(&shift))?));
+    }
+
+    /* data processing: <opcode>{cond}{s} */
+    for (opcode, base) in OPCODES.iter().enumerate() {
+        if let Some((cond, s)) = mnemonic.strip_prefix(base).and_then(|suffix| parse_suffix(suffix, "s")) {
+            let opcode = opcode as u32;
+            let registers = if matches!(opcode, 8..=11 | 13 | 15) {1} else {2};
+            if operands.len() <= registers {return Err(format!("Expected at least {} operands, found {}", registers + 1, operands.len()))}
+            let (rd, rn, s) = match opcode {
+                /* tst, teq, cmp, cmn: <rn>, <operand2> (always set flags) */
+                8..=11 => (0, parse_register(operands[0])?, true),
+                /* mov, mvn: <rd>, <operand2> */
+                13 | 15 => (parse_register(operands[0])?, 0, s),
+                /* <rd>, <rn>, <operand2> */
+                _ => (parse_register(operands[0])?, parse_register(operands[1])?, s)
+            };
+            let operand2 = encode_operand2(&operands[registers..])?;
+            return Ok(encode_data_processing(cond, opcode, s, rn, rd, operand2));
+        }
+    }
+
+    Err(format!("Unknown mnemonic: {}", mnemonic))
+}
+
+/* Encode a single data transfer instruction
+cond     <-  condition code
+load     <-  ldr (true) or str (false)
+byte     <-  transfer a single byte
+operands <-  Rd followed by the address operands
+addr     <-  the address the instruction will be loaded at
+pool     <-  literal pool for ldr rd, =<expr> constants */
+fn encode_transfer(cond : u32, load : bool, byte : bool, operands : &[&str], addr : u32, pool : &mut LiteralPool) -> Result<u32, String> {
+    if operands.len() < 2 {return Err(format!("Expected at least 2 operands, found {}", operands.len()))}
+    let rd = parse_register(operands[0])?;
+
+    /* ldr rd, =<expr> loads a constant, assembled as a mov when it fits in an immediate, otherwise as
+    ldr rd, [pc, #offset] from the literal pool (the PC reads as the instruction address + 8) */
+    if let Some(literal) = operands[1].strip_prefix('=') {
+        if !load || byte {return Err(format!("Literals can only be loaded with ldr: {}", operands[1]))}
+        let value = parse_number(literal).ok_or(format!("Invalid literal: {}", literal))?;
+        if let Some(immediate) = encode_immediate(value) {return Ok(encode_data_processing(cond, 13, false, 0, rd, 1 << 25 | immediate))}
+        /* the pool is behind the PC when this is the last instruction, so the offset may be negative (U clear) */
+        let offset = pool.address(value) as i64 - (addr as i64 + 8);
+        let (up, offset) = (offset >= 0, offset.unsigned_abs() as u32);
+        if offset > MAX_TRANSFER_OFFSET {return Err(format!("Literal pool is out of range ({:#x} bytes away)", offset))}
+        return Ok(cond << 28 | 1 << 26 | 1 << 24 | (up as u32) << 23 | 1 << 20 | 15 << 16 | rd << 12 | offset);
+    }
+
+    let address = operands[1];
+    let writeback = address.ends_with('!');
+    let inner = address.trim_end_matches('!').strip_prefix('[').and_then(|a| a.strip_suffix(']'))
+        .ok_or(format!("Invalid address: {}", address))?;
+    let inner = split_operands(inner);
+    let rn = parse_register(inner.first().copied().unwrap_or(""))?;
+
+    /* pre-indexed [rn, <offset>]{!}, or post-indexed [rn], <offset> */
+    let (pre, offset) = match (inner.len(), operands.len()) {
+        (_, 2) => (true, &inner[1..]),
+        (1, _) => (false, &operands[2..]),
+        _ => return Err(format!("Invalid address: {}", address))
+    };
+
+    let (register, up, offset) = match offset.first() {
+        None => (false, true, 0),
+        Some(first) => match first.strip_prefix('#') {
+            Some(imm) => {
+                let (up, imm) = match imm.strip_prefix('-') {Some(imm) => (false, imm), None => (true, imm)};
+                let imm = parse_number(imm).ok_or(format!("Invalid offset: {}", first))?;
+                if imm > 0xFFF {return Err(format!("Offset {:#x} does not fit in 12 bits", imm))}
+                (false, up, imm)
+            },
+            None => {
+                let (up, rm) = match first.strip_prefix('-') {Some(rm) => (false, rm), None => (true, first.strip_prefix('+').unwrap_or(first))};
+                let mut shifted = vec![rm];
+                shifted.extend_from_slice(&offset[1..]);
+                let shifted = encode_operand2(&shifted)?;
+                /* bit 4 set here is the undefined instruction space, transfers only shift by an immediate */
+                if shifted & 1 << 4 != 0 {return Err(format!("Offsets cannot be shifted by a register: {}", offset.join(", ")))}
+                (true, up, shifted)
+            }
+        }
+    };
+
+    Ok(cond << 28 | 1 << 26 | (register as u32) << 25 | (pre as u32) << 24 | (up as u32) << 23 | (byte as u32) << 22
+        | (writeback as u32) << 21 | (load as u32) << 20 | rn << 16 | rd << 12 | offset)
+}
+
+/* Encode the second operand of a data processing instruction, including the I bit (25)
+operands <-  either #<expr>, or <rm>{, <shift> #<expr> | <shift> <rs> | rrx} */
+fn encode_operand2(operands : &[&str]) -> Result<u32, String> {
+    match operands {
+        [imm] if imm.starts_with('#') => {
+            let value = parse_number(&imm[1..]).ok_or(format!("Invalid immediate: {}", imm))?;
+            Ok(1 << 25 | encode_immediate(value).ok_or(format!("Immediate {:#x} cannot be encoded", value))?)
+        },
+        [rm] => parse_register(rm),
+        [rm, shift] if shift.eq_ignore_ascii_case("rrx") => Ok(0b11 << 5 | parse_register(rm)?),
+        [rm, shift] => {
+            let rm = parse_register(rm)?;
+            let (kind, amount) = match shift.find(char::is_whitespace) {
+                Some(ind) => (shift[..ind].to_lowercase(), shift[ind..].trim()),
+                None => return Err(format!("Invalid shift: {}", shift))
+            };
+            let kind = SHIFTS.iter().position(|s| *s == kind).ok_or(format!("Invalid shift: {}", shift))? as u32;
+            match amount.strip_prefix('#') {
+                Some(amount) => {
+                    /* lsr/asr by 32 are encoded as an amount of 0, while a zero shift of any kind is lsl #0 */
+                    let limit = if kind == 1 || kind == 2 {32} else {31};
+                    let amount = parse_number(amount).filter(|a| *a <= limit).ok_or(format!("Invalid shift amount: {}", amount))?;
+                    Ok(if amount == 0 {rm} else {(amount & 0x1F) << 7 | kind << 5 | rm})
+                },
+                None => Ok(parse_register(amount)? << 8 | kind << 5 | 1 << 4 | rm)
+            }
+        },
+        _ => Err(format!("Invalid operand: {}", operands.join(", ")))
+    }
+}
+
+/* Find the 8 bit value and 4 bit rotation (value rotated right by twice the rotation) for a constant
+value   <-  the constant to encode
+return  <-  bits 0-11 of the instruction, or None if the constant cannot be represented */
+fn encode_immediate(value : u32) -> Option<u32> {
+    (0..16).find(|rotate| value.rotate_left(rotate * 2) <= 0xFF).map(|rotate| rotate << 8 | value.rotate_left(rotate * 2))
+}
+
+// INSTRUCTION ENCODING=========================================================
+/* Encode a data processing instruction, e.g. for building instruction words in tests
+cond     <-  condition code
+opcode   <-  the 4 bit opcode (the index of its mnemonic in OPCODES)
+s        <-  set the flags
+rn, rd   <-  first operand and destination register numbers (0 when unused)
+operand2 <-  bits 0-11 of the instruction including the I bit (25) for a rotated immediate */
+pub const fn encode_data_processing(cond : u32, opcode : u32, s : bool, rn : u32, rd : u32, operand2 : u32) -> u32 {
+    cond << 28 | opcode << 21 | (s as u32) << 20 | rn << 16 | rd << 12 | operand2
+}
+
+/* Encode a branch. The offset must be word aligned and within -0x2000000 to 0x1FFFFFC, other offsets lose bits
+0-1 and 26-31 (a different target) without an error, use checked_encode_branch for offsets that may not fit.
+link    <-  bl (true) or b (false)
+offset  <-  byte offset of the target from the instruction address + 8, as in DecodedInstruction::Branch */
+pub const fn encode_branch(cond : u32, link : bool, offset : i32) -> u32 {
+    cond << 28 | 0b101 << 25 | (link as u32) << 24 | ((offset >> 2) as u32 & 0xFF_FFFF)
+}
+
+/* Encode a branch as encode_branch does
+return  <-  None if the offset is not word aligned or out of range */
+pub const fn checked_encode_branch(cond : u32, link : bool, offset : i32) -> Option<u32> {
+    if offset % 4 != 0 || offset < MIN_BRANCH_OFFSET || offset > MAX_BRANCH_OFFSET {return None}
+    Some(encode_branch(cond, link, offset))
+}
+
+/* Encode a multiply, mla (Rd = Rm * Rs + Rn) when accumulate is set, otherwise mul (Rn is ignored) */
+pub const fn encode_multiply(cond : u32, accumulate : bool, s : bool, rd : u32, rn : u32, rs : u32, rm : u32) -> u32 {
+    cond << 28 | (accumulate as u32) << 21 | (s as u32) << 20 | rd << 16 | rn << 12 | rs << 8 | 0b1001 << 4 | rm
+}
+
+/* Encode a software interrupt
+comment <-  the 24 bit comment field (0 writes the low byte of r0 as a character, 1 halts) */
+pub const fn encode_swi(cond : u32, comment : u32) -> u32 {cond << 28 | 0b1111 << 24 | comment}
+
+// PARSING UTILITIES============================================================
+/* Split operands on commas that are not inside square brackets */
+fn split_operands(text : &str) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (ind, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' if depth == 0 => {
+                operands.push(text[start..ind].trim());
+                start = ind + 1;
+            },
+            _ => ()
+        }
+    }
+    if !text[start..].trim().is_empty() {operands.push(text[start..].trim());}
+    operands
+}
+
+/* Check the number of operands an instruction was given */
+fn expect_operands(operands : &[&str], n : usize) -> Result<(), String> {
+    if operands.len() == n {Ok(())} else {Err(format!("Expected {} operands, found {}", n, operands.len()))}
+}
+
+/* Parse a condition code suffix, empty meaning always (hs and lo are aliases of cs and cc) */
+fn parse_cond(suffix : &str) -> Option<u32> {
+    match suffix {
+        "" => Some(AL),
+        "hs" => Some(2),
+        "lo" => Some(3),
+        _ => CONDITIONS.iter().position(|c| *c == suffix).map(|c| c as u32)
+    }
+}
+
+/* Parse a mnemonic suffix made of an optional condition and an optional flag letter in either order
+suffix  <-  the mnemonic with its base removed
+flag    <-  the flag letter accepted ("s" or "b")
+return  <-  the condition code and whether the flag was present */
+fn parse_suffix(suffix : &str, flag : &str) -> Option<(u32, bool)> {
+    parse_cond(suffix).map(|cond| (cond, false))
+        .or_else(|| suffix.strip_prefix(flag).and_then(parse_cond).map(|cond| (cond, true)))
+        .or_else(|| suffix.strip_suffix(flag).and_then(parse_cond).map(|cond| (cond, true)))
+}
+
+/* Parse a register name (r0-r15, sp, lr, pc) */
+fn parse_register(name : &str) -> Result<u32, String> {parse_reg(name).ok_or(format!("Invalid register: {}", name))}
+
+/* Parse a decimal or 0x prefixed hexadecimal number, negative values wrap to their two's complement */
+pub(crate) fn parse_number(text : &str) -> Option<u32> {
+    let (negative, text) = match text.trim().strip_prefix('-') {Some(text) => (true, text), None => (false, text.trim())};
+    let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => text.parse().ok()?
+    };
+    Some(if negative {value.wrapping_neg()} else {value})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CPU, decode::DecodedInstruction};
+
+    /* Assemble a program into its instruction words */
+    fn words(source : &str) -> Vec<u32> {
+        assemble(source).unwrap().chunks_exact(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect()
+    }
+
+    #[test]
+    fn literal_that_fits_an_immediate_is_a_mov() {
+        assert_eq!(words("ldr r0, =0xFF00"), vec![0xE3A0_0CFF]);
+    }
+
+    #[test]
+    fn literal_is_loaded_from_the_pool_after_the_code() {
+        /* ldr r0, [pc, #0] reads 0x8, then the halt, then the pool */
+        assert_eq!(words("ldr r0, =0x12345678\nhalt"), vec![0xE59F_0000, HALT, 0x1234_5678]);
+        /* repeated constants share a pool entry */
+        assert_eq!(words("ldr r0, =0x12345678\nldr r1, =0x12345678\nhalt"), vec![0xE59F_0004, 0xE59F_1000, HALT, 0x1234_5678]);
+    }
+
+    #[test]
+    fn literal_in_the_last_instruction_uses_a_negative_offset() {
+        /* the pool at 0x4 is 4 bytes behind the PC (0x0 + 8) */
+        assert_eq!(words("ldr r0, =0x12345678"), vec![0xE51F_0004, 0x1234_5678]);
+    }
+
+    #[test]
+    fn literals_are_only_loaded_with_ldr() {
+        assert!(assemble("str r0, =0x12345678").is_err());
+        assert!(assemble("ldrb r0, =0x12345678").is_err());
+    }
+
+    #[test]
+    fn transfer_offsets_cannot_be_shifted_by_a_register() {
+        assert_eq!(assemble("ldr r0, [r1, r2, lsl r3]").unwrap_err().to_string(), "line 1: Offsets cannot be shifted by a register: r2, lsl r3");
+        assert_eq!(words("ldr r0, [r1, r2, lsl #3]"), vec![0xE791_0182]);
+    }
+
+    /* Decode words through CPU::instructions, as a loaded program would be */
+    fn decoded(words : &[u32]) -> Vec<DecodedInstruction> {
+        let mut cpu = CPU::with_memory(words.len() * 4);
+        cpu.poke_words(&words.iter().enumerate().map(|(ind, word)| (ind * 4, *word)).collect::<Vec<_>>()).unwrap();
+        cpu.instructions().map(|(_, instruction)| instruction).collect()
+    }
+
+    #[test]
+    fn encoders_round_trip_through_the_decoder() {
+        assert_eq!(decoded(&[
+            encode_data_processing(14, 4, true, 1, 2, 1 << 25 | 0x3FF),
+            encode_data_processing(0, 13, false, 0, 3, 0x104),
+            encode_branch(1, false, -0x14),
+            encode_branch(14, true, 0x1FF_FFFC),
+            encode_branch(14, false, -0x200_0000),
+            encode_multiply(11, true, false, 4, 5, 6, 7),
+            encode_swi(14, 0xABCDEF)
+        ]), vec![
+            /* the immediate decodes to its value, 0xFF rotated right by 6 */
+            DecodedInstruction::DataProcessing {cond : 14, opcode : 4, set_flags : true, rn : 1, rd : 2, immediate : true, operand2 : 0xFC00_0003},
+            DecodedInstruction::DataProcessing {cond : 0, opcode : 13, set_flags : false, rn : 0, rd : 3, immediate : false, operand2 : 0x104},
+            DecodedInstruction::Branch {cond : 1, link : false, offset : -0x14},
+            DecodedInstruction::Branch {cond : 14, link : true, offset : 0x1FF_FFFC},
+            DecodedInstruction::Branch {cond : 14, link : false, offset : -0x200_0000},
+            DecodedInstruction::Multiply {cond : 11, accumulate : true, set_flags : false, rd : 4, rn : 5, rs : 6, rm : 7},
+            DecodedInstruction::SoftwareInterrupt {cond : 14, comment : 0xABCDEF}
+        ]);
+    }
+
+    #[test]
+    fn checked_branches_reject_offsets_that_do_not_fit() {
+        assert_eq!(checked_encode_branch(14, false, -0x14), Some(encode_branch(14, false, -0x14)));
+        assert_eq!(checked_encode_branch(14, false, 0x1FF_FFFC), Some(0xEA7F_FFFF));
+        assert_eq!(checked_encode_branch(14, false, 0x200_0000), None);
+        assert_eq!(checked_encode_branch(14, false, -0x200_0004), None);
+        assert_eq!(checked_encode_branch(14, true, 2), None);
+    }
+}
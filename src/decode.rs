@@ -0,0 +1,262 @@
+use crate::{CPU, InstructionType, instruction_type, get_bits, get_bit, PC, SWI_WRITE_CHAR, SWI_HALT};
+
+// DECODED INSTRUCTIONS=========================================================
+/* An instruction word split into its fields, register fields are register numbers (0-15) and cond is
+the condition code held in bits 28-31 */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodedInstruction {
+    SoftwareInterrupt {cond : u32, comment : u32},
+    /* offset is the sign extended byte offset from the instruction address + 8 */
+    Branch {cond : u32, link : bool, offset : i32},
+    BlockDataTransfer {cond : u32, load : bool, pre : bool, up : bool, writeback : bool, rn : usize, registers : u16},
+    /* offset is the 12 bit immediate, or the shifted register encoding when register_offset is set */
+    SingleDataTransfer {cond : u32, load : bool, byte : bool, pre : bool, up : bool, writeback : bool, rn : usize, rd : usize, register_offset : bool, offset : u32},
+    /* offset is the 8 bit immediate, or the offset register number when register_offset is set */
+    HalfwordDataTransfer {cond : u32, load : bool, signed : bool, halfword : bool, pre : bool, up : bool, writeback : bool, rn : usize, rd : usize, register_offset : bool, offset : u32},
+    Multiply {cond : u32, accumulate : bool, set_flags : bool, rd : usize, rn : usize, rs : usize, rm : usize},
+    LongMultiply {cond : u32, signed : bool, accumulate : bool, set_flags : bool, rdhi : usize, rdlo : usize, rs : usize, rm : usize},
+    BranchExchange {cond : u32, rm : usize},
+    /* op is bits 21-22: qadd, qsub, qdadd, qdsub */
+    SaturatingArithmetic {cond : u32, op : u32, rd : usize, rn : usize, rm : usize},
+    CountLeadingZeros {cond : u32, rd : usize, rm : usize},
+    MoveFromStatus {cond : u32, rd : usize},
+    /* fields is the 4 bit field mask (c, x, s, f), operand is the rotated immediate or the source register number */
+    MoveToStatus {cond : u32, fields : u32, immediate : bool, operand : u32},
+    /* operand2 is the rotated immediate, or the shifted register encoding (bits 0-11) when immediate is clear */
+    DataProcessing {cond : u32, opcode : u32, set_flags : bool, rn : usize, rd : usize, immediate : bool, operand2 : u32},
+    /* any CDP, MRC/MCR or LDC/STC, cp_num is the coprocessor number */
+    Coprocessor {cond : u32, cp_num : u32},
+    Unknown(u32)
+}
+
+/* Split an instruction word into its fields, classified the same way as during execution
+instruction <-  the instruction word */
+pub fn decode(instruction : u32) -> DecodedInstruction {
+    let word = &instruction;
+    let cond = get_bits(word, 28, 4);
+    let reg = |start : u32| get_bits(word, start, 4) as usize;
+
+    match instruction_type(word) {
+        InstructionType::SoftwareInterrupt => DecodedInstruction::SoftwareInterrupt {cond, comment : get_bits(word, 0, 24)},
+        InstructionType::Branch => DecodedInstruction::Branch {
+            cond,
+            link : get_bit(word, 24),
+            offset : ((get_bits(word, 0, 24) << 8) as i32) >> 6
+        },
+        InstructionType::BlockDataTransfer => DecodedInstruction::BlockDataTransfer {
+            cond,
+            load : get_bit(word, 20),
+            pre : get_bit(word, 24),
+            up : get_bit(word, 23),
+            writeback : get_bit(word, 21),
+            rn : reg(16),
+            registers : get_bits(word, 0, 16) as u16
+        },
+        InstructionType::SingleDataTransfer => DecodedInstruction::SingleDataTransfer {
+            cond,
+            load : get_bit(word, 20),
+            byte : get_bit(word, 22),
+            pre : get_bit(word, 24),
+            up : get_bit(word, 23),
+            writeback : get_bit(word, 21),
+            rn : reg(16),
+            rd : reg(12),
+            register_offset : get_bit(word, 25),
+            offset : get_bits(word, 0, 12)
+        },
+        InstructionType::HalfwordDataTransfer => DecodedInstruction::HalfwordDataTransfer {
+            cond,
+            load : get_bit(word, 20),
+            signed : get_bit(word, 6),
+            halfword : get_bit(word, 5),
+            pre : get_bit(word, 24),
+            up : get_bit(word, 23),
+            writeback : get_bit(word, 21),
+            rn : reg(16),
+            rd : reg(12),
+            register_offset : !get_bit(word, 22),
+            offset : if get_bit(word, 22) {get_bits(word, 8, 4) << 4 | get_bits(word, 0, 4)} else {get_bits(word, 0, 4)}
+        },
+        InstructionType::Multiply => DecodedInstruction::Multiply {
+            cond,
+            accumulate : get_bit(word, 21),
+            set_flags : get_bit(word, 20),
+            rd : reg(16),
+            rn : reg(12),
+            rs : reg(8),
+            rm : reg(0)
+        },
+        InstructionType::LongMultiply => DecodedInstruction::LongMultiply {
+            cond,
+            signed : get_bit(word, 22),
+            accumulate : get_bit(word, 21),
+            set_flags : get_bit(word, 20),
+            rdhi : reg(16),
+            rdlo : reg(12),
+            rs : reg(8),
+            rm : reg(0)
+        },
+        InstructionType::BranchExchange => DecodedInstruction::BranchExchange {cond, rm : reg(0)},
+        InstructionType::SaturatingArithmetic => DecodedInstruction::SaturatingArithmetic {cond, op : get_bits(word, 21, 2), rd : reg(12), rn : reg(16), rm : reg(0)},
+        InstructionType::CountLeadingZeros => DecodedInstruction::CountLeadingZeros {cond, rd : reg(12), rm : reg(0)},
+        InstructionType::MoveFromStatus => DecodedInstruction::MoveFromStatus {cond, rd : reg(12)},
+        InstructionType::MoveToStatus => DecodedInstruction::MoveToStatus {
+            cond,
+            fields : get_bits(word, 16, 4),
+            immediate : get_bit(word, 25),
+            operand : if get_bit(word, 25) {
+                get_bits(word, 0, 8).rotate_right(get_bits(word, 8, 4) * 2)
+            } else {get_bits(word, 0, 4)}
+        },
+        InstructionType::DataProcessing => DecodedInstruction::DataProcessing {
+            cond,
+            opcode : get_bits(word, 21, 4),
+            set_flags : get_bit(word, 20),
+            rn : reg(16),
+            rd : reg(12),
+            immediate : get_bit(word, 25),
+            operand2 : if get_bit(word, 25) {
+                get_bits(word, 0, 8).rotate_right(get_bits(word, 8, 4) * 2)
+            } else {get_bits(word, 0, 12)}
+        },
+        InstructionType::Coprocessor => DecodedInstruction::Coprocessor {cond, cp_num : get_bits(word, 8, 4)},
+        InstructionType::Unknown => DecodedInstruction::Unknown(instruction)
+    }
+}
+
+impl CPU {
+    /* Walk every word of memory in address order, decoding each without executing it
+    return  <-  (address, decoded instruction) pairs, words are read little endian */
+    pub fn instructions(&self) -> impl Iterator<Item = (usize, DecodedInstruction)> + '_ {
+        self.memory.chunks_exact(4).enumerate()
+            .map(|(ind, word)| (ind * 4, decode(u32::from_le_bytes([word[0], word[1], word[2], word[3]]))))
+    }
+}
+
+// STATIC VALIDATION============================================================
+/* An instruction that would raise a RuntimeError if it were executed, found without running the program */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationWarning {
+    pub addr : usize,
+    pub instruction : u32,
+    pub reason : &'static str
+}
+
+impl CPU {
+    /* Check every non-zero word of memory for encodings that fault when executed (the same checks the
+    instruction handlers make at runtime). Data words are checked too, so warnings outside the code are expected.
+    return  <-  a warning for each faulting word, in address order */
+    pub fn validate(&self) -> Vec<ValidationWarning> {
+        self.memory.chunks_exact(4).enumerate()
+            .map(|(ind, word)| (ind * 4, u32::from_le_bytes([word[0], word[1], word[2], word[3]])))
+            .filter(|(_, instruction)| *instruction != 0)
+            .filter(|(_, instruction)| !(self.skip_coprocessor && instruction_type(instruction) == InstructionType::Coprocessor))
+            .filter_map(|(addr, instruction)| {
+                check(addr, instruction, self.memory.len()).map(|reason| ValidationWarning {addr, instruction, reason})
+            })
+            .collect()
+    }
+}
+
+/* Find the first reason an instruction would fault
+addr        <-  address of the instruction
+instruction <-  the instruction word
+memsize     <-  size of memory, for branch targets */
+fn check(addr : usize, instruction : u32, memsize : usize) -> Option<&'static str> {
+    match decode(instruction) {
+        DecodedInstruction::SoftwareInterrupt {comment, ..} if comment != SWI_WRITE_CHAR && comment != SWI_HALT => Some("Unknown software interrupt"),
+        DecodedInstruction::Branch {offset, ..} => {
+            let target = (addr as u32).wrapping_add(8).wrapping_add(offset as u32);
+            if !target.is_multiple_of(4) || (target as usize).saturating_add(4) > memsize {Some("Branch target is outside memory or unaligned")} else {None}
+        },
+        DecodedInstruction::BlockDataTransfer {rn, ..} if rn == PC => Some("Block Data Transfer instruction uses PC as Rn"),
+        DecodedInstruction::SingleDataTransfer {rd, ..} if rd == PC => Some("Data Transfer instruction uses PC as Rd"),
+        DecodedInstruction::SingleDataTransfer {rn, pre, writeback, ..} if rn == PC && (!pre || writeback) => Some("Data Transfer instruction writes back to PC"),
+        DecodedInstruction::SingleDataTransfer {rn, rd, pre, writeback, ..} if rn == rd && (!pre || writeback) => Some("Data Transfer instruction writes back to Rd"),
+        DecodedInstruction::SingleDataTransfer {register_offset : true, pre, rd, offset, ..} => {
+            if get_bits(&offset, 0, 4) as usize == rd && !pre {Some("Data Transfer instruction uses same register as Rn, Rm")}
+            else if get_bits(&offset, 0, 4) as usize == PC {Some("Data Transfer instruction uses PC as Rm")}
+            else {check_shift(offset)}
+        },
+        DecodedInstruction::HalfwordDataTransfer {rd, ..} if rd == PC => Some("Halfword Data Transfer instruction uses PC as Rd"),
+        DecodedInstruction::HalfwordDataTransfer {rn, pre, writeback, ..} if rn == PC && (!pre || writeback) => Some("Halfword Data Transfer instruction writes back to PC"),
+        DecodedInstruction::HalfwordDataTransfer {rn, rd, pre, writeback, ..} if rn == rd && (!pre || writeback) => Some("Halfword Data Transfer instruction writes back to Rd"),
+        DecodedInstruction::HalfwordDataTransfer {register_offset : true, offset, ..} if offset as usize == PC => Some("Halfword Data Transfer instruction uses PC as Rm"),
+        DecodedInstruction::HalfwordDataTransfer {load : false, signed : true, ..} => Some("Halfword Data Transfer instruction stores a signed value"),
+        DecodedInstruction::Multiply {rd, rn, rs, rm, ..} if rd == rm || [rd, rn, rs, rm].contains(&PC) => Some("Multiply instruction uses same register for Rd, Rm"),
+        DecodedInstruction::LongMultiply {rdhi, rdlo, rs, rm, ..} if [rdhi, rdlo, rs, rm].contains(&PC) => Some("Long Multiply instruction uses PC"),
+        DecodedInstruction::LongMultiply {rdhi, rdlo, rm, ..} if rdhi == rdlo || rdhi == rm || rdlo == rm => Some("Long Multiply instruction uses same register for RdHi, RdLo, Rm"),
+        DecodedInstruction::SaturatingArithmetic {rd, rn, rm, ..} if [rd, rn, rm].contains(&PC) => Some("Saturating instruction uses PC"),
+        DecodedInstruction::CountLeadingZeros {rd, rm, ..} if rd == PC || rm == PC => Some("CLZ instruction uses PC as Rd or Rm"),
+        DecodedInstruction::MoveFromStatus {..} | DecodedInstruction::MoveToStatus {..} if get_bit(&instruction, 22) => Some("SPSR is not available"),
+        DecodedInstruction::MoveFromStatus {rd, ..} if rd == PC => Some("MRS instruction uses PC as Rd"),
+        DecodedInstruction::DataProcessing {immediate : false, operand2, ..} => check_shift(operand2),
+        DecodedInstruction::Coprocessor {..} => Some("Coprocessor instructions are not supported"),
+        DecodedInstruction::Unknown(_) => Some("Invalid instruction type"),
+        _ => None
+    }
+}
+
+/* Check a shifted register operand (bits 0-11) as shift_operation does
+operand <-  the shifted register encoding */
+fn check_shift(operand : u32) -> Option<&'static str> {
+    if get_bit(&operand, 4) && (get_bits(&operand, 0, 4) as usize == PC || get_bits(&operand, 8, 4) as usize == PC) {Some("Register shift uses PC as Rm or Rs")}
+    else if get_bit(&operand, 4) && get_bit(&operand, 7) {Some("Shift neither by constant, nor by register")}
+    else {None}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn instructions_decode_without_executing() {
+        let binary = assemble("mov r0, #1\nldr r1, [r0, #4]\nmul r2, r0, r1\nbne 0x0\nhalt").unwrap();
+        let mut cpu = CPU::with_memory(binary.len());
+        cpu.load_program_from_reader(&mut &binary[..]).unwrap();
+        let decoded : Vec<(usize, DecodedInstruction)> = cpu.instructions().collect();
+        assert_eq!(decoded, vec![
+            (0x0, DecodedInstruction::DataProcessing {cond : 14, opcode : 13, set_flags : false, rn : 0, rd : 0, immediate : true, operand2 : 1}),
+            (0x4, DecodedInstruction::SingleDataTransfer {cond : 14, load : true, byte : false, pre : true, up : true, writeback : false, rn : 0, rd : 1, register_offset : false, offset : 4}),
+            (0x8, DecodedInstruction::Multiply {cond : 14, accumulate : false, set_flags : false, rd : 2, rn : 0, rs : 1, rm : 0}),
+            (0xC, DecodedInstruction::Branch {cond : 1, link : false, offset : -0x14}),
+            (0x10, DecodedInstruction::SoftwareInterrupt {cond : 14, comment : 1})
+        ]);
+        assert_eq!(cpu.registers, [0; 16]);
+    }
+
+    #[test]
+    fn validate_flags_broken_instructions() {
+        let mut cpu = CPU::with_memory(0x100);
+        cpu.poke_words(&[
+            /* mov r0, #1 */
+            (0x0, 0xE3A0_0001),
+            /* mul r0, r0, r1 */
+            (0x4, 0xE000_0190),
+            /* mov r0, r1, lsl pc */
+            (0x8, 0xE1A0_0F11),
+            /* b 0x1000 */
+            (0xC, 0xEA00_03FB),
+            /* swi 5 */
+            (0x10, 0xEF00_0005),
+            /* halt */
+            (0x14, crate::HALT)
+        ]).unwrap();
+        let warnings : Vec<(usize, &str)> = cpu.validate().iter().map(|warning| (warning.addr, warning.reason)).collect();
+        assert_eq!(warnings, vec![
+            (0x4, "Multiply instruction uses same register for Rd, Rm"),
+            (0x8, "Register shift uses PC as Rm or Rs"),
+            (0xC, "Branch target is outside memory or unaligned"),
+            (0x10, "Unknown software interrupt")
+        ]);
+    }
+
+    #[test]
+    fn register_shifted_transfer_offsets_are_undefined() {
+        /* ldr r0, [r1, r2, lsl r3] would set bit 4, which is the undefined instruction space */
+        assert_eq!(decode(0xE791_0312), DecodedInstruction::Unknown(0xE791_0312));
+        assert_eq!(decode(0xE7F0_00F0), DecodedInstruction::Unknown(0xE7F0_00F0));
+        assert_eq!(decode(0xEE00_0100), DecodedInstruction::Coprocessor {cond : 14, cp_num : 1});
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{CPU, Endian, InstructionType, instruction_type, get_bits, get_bit, reg_name, special_address_name, PC, asm::{CONDITIONS, OPCODES, SHIFTS}};
+
+// DISASSEMBLER=================================================================
+/* Disassemble a single instruction word into ARM assembly, e.g. "add r0, r1, #4".
+Branch targets are shown as if the instruction were at address 0.
+instruction <-  the instruction word */
+pub fn disassemble_word(instruction : u32) -> String {disassemble_at(&instruction, 0)}
+
+impl CPU {
+    /* Disassemble the words in memory from start up to (not including) end. PC relative loads of a peripheral
+    register's address are annotated with its name, e.g. "ldr r0, [pc, #4]  ; GPIO SET".
+    start   <-  address of the first instruction (word aligned)
+    end     <-  address to stop at
+    return  <-  one line of assembly per word (or the access error for an unaligned start) */
+    pub fn disassemble_range(&self, start : usize, end : usize) -> Vec<String> {
+        (start..end.min(self.memory.len().saturating_sub(3))).step_by(4)
+            .map(|loc| self.read_word(loc, Endian::Little).map_or_else(|err| err.to_string(), |word| {
+                match self.literal_value(loc as u32, &word).and_then(special_address_name) {
+                    Some(name) => format!("{}  ; {}", disassemble_at(&word, loc as u32), name),
+                    None => disassemble_at(&word, loc as u32)
+                }
+            }))
+            .collect()
+    }
+
+    /* Get the constant loaded by a PC relative ldr with an immediate offset
+    addr    <-  address of the instruction */
+    fn literal_value(&self, addr : u32, instruction : &u32) -> Option<u32> {
+        let literal = instruction_type(instruction) == InstructionType::SingleDataTransfer && get_bits(instruction, 16, 4) as usize == PC
+            && get_bit(instruction, 20) && !get_bit(instruction, 22) && !get_bit(instruction, 25);
+        if !literal {return None}
+        let offset = get_bits(instruction, 0, 12);
+        let base = addr.wrapping_add(8);
+        self.get_mem_word(if get_bit(instruction, 23) {base.wrapping_add(offset)} else {base.wrapping_sub(offset)} as usize).ok()
+    }
+}
+
+/* Disassemble an instruction located at a given address
+instruction <-  the instruction word
+addr        <-  address of the instruction, used to resolve branch targets */
+pub(crate) fn disassemble_at(instruction : &u32, addr : u32) -> String {
+    let cond = match get_bits(instruction, 28, 4) {
+        14 => "",
+        15 => "nv",
+        cond => CONDITIONS[cond as usize]
+    };
+    let rd = reg_name(get_bits(instruction, 12, 4));
+    let rn = reg_name(get_bits(instruction, 16, 4));
+
+    match instruction_type(instruction) {
+        InstructionType::SoftwareInterrupt => format!("swi{} {}", cond, number(get_bits(instruction, 0, 24))),
+        InstructionType::Branch => {
+            let offset = ((get_bits(instruction, 0, 24) << 8) as i32) >> 6;
+            let link = if get_bit(instruction, 24) {"l"} else {""};
+            format!("b{}{} {:#x}", link, cond, addr.wrapping_add(8).wrapping_add(offset as u32))
+        },
+        InstructionType::BlockDataTransfer => {
+            let name = if get_bit(instruction, 20) {"ldm"} else {"stm"};
+            let mode = match (get_bit(instruction, 24), get_bit(instruction, 23)) {
+                (false, true) => "ia",
+                (true, true) => "ib",
+                (false, false) => "da",
+                (true, false) => "db"
+            };
+            let list : Vec<&str> = (0..16).filter(|r| get_bit(instruction, *r)).map(reg_name).collect();
+            format!("{}{}{} {}{}, {{{}}}", name, cond, mode, rn, if get_bit(instruction, 21) {"!"} else {""}, list.join(", "))
+        },
+        InstructionType::SingleDataTransfer => {
+            let name = if get_bit(instruction, 20) {"ldr"} else {"str"};
+            let byte = if get_bit(instruction, 22) {"b"} else {""};
+            let offset = if get_bit(instruction, 25) {
+                format!("{}{}", if get_bit(instruction, 23) {""} else {"-"}, shifted_register(instruction))
+            } else {
+                format!("#{}{}", if get_bit(instruction, 23) {""} else {"-"}, number(get_bits(instruction, 0, 12)))
+            };
+            format!("{}{}{} {}, {}", name, cond, byte, rd, address(instruction, rn, &offset, get_bits(instruction, 0, 12) == 0 && !get_bit(instruction, 25)))
+        },
+        InstructionType::HalfwordDataTransfer => {
+            let name = match (get_bit(instruction, 20), get_bit(instruction, 6), get_bit(instruction, 5)) {
+                (true, true, false) => "ldrsb",
+                (true, true, true) => "ldrsh",
+                (true, false, _) => "ldrh",
+                _ => "strh"
+            };
+            let sign = if get_bit(instruction, 23) {""} else {"-"};
+            let (offset, zero) = if get_bit(instruction, 22) {
+                let imm = get_bits(instruction, 8, 4) << 4 | get_bits(instruction, 0, 4);
+                (format!("#{}{}", sign, number(imm)), imm == 0)
+            } else {
+                (format!("{}{}", sign, reg_name(get_bits(instruction, 0, 4))), false)
+            };
+            format!("{}{} {}, {}", name, cond, rd, address(instruction, rn, &offset, zero))
+        },
+        InstructionType::Multiply => {
+            let s = if get_bit(instruction, 20) {"s"} else {""};
+            let (rd, rn, rs, rm) = (reg_name(get_bits(instruction, 16, 4)), reg_name(get_bits(instruction, 12, 4)), reg_name(get_bits(instruction, 8, 4)), reg_name(get_bits(instruction, 0, 4)));
+            if get_bit(instruction, 21) {
+                format!("mla{}{} {}, {}, {}, {}", cond, s, rd, rm, rs, rn)
+            } else {
+                format!("mul{}{} {}, {}, {}", cond, s, rd, rm, rs)
+            }
+        },
+        InstructionType::LongMultiply => {
+            let name = match (get_bit(instruction, 22), get_bit(instruction, 21)) {
+                (false, false) => "umull",
+                (false, true) => "umlal",
+                (true, false) => "smull",
+                (true, true) => "smlal"
+            };
+            let s = if get_bit(instruction, 20) {"s"} else {""};
+            format!("{}{}{} {}, {}, {}, {}", name, cond, s, rd, rn, reg_name(get_bits(instruction, 0, 4)), reg_name(get_bits(instruction, 8, 4)))
+        },
+        InstructionType::BranchExchange => format!("bx{} {}", cond, reg_name(get_bits(instruction, 0, 4))),
+        InstructionType::SaturatingArithmetic => {
+            let name = ["qadd", "qsub", "qdadd", "qdsub"][get_bits(instruction, 21, 2) as usize];
+            format!("{}{} {}, {}, {}", name, cond, rd, reg_name(get_bits(instruction, 0, 4)), rn)
+        },
+        InstructionType::CountLeadingZeros => format!("clz{} {}, {}", cond, rd, reg_name(get_bits(instruction, 0, 4))),
+        InstructionType::MoveFromStatus => format!("mrs{} {}, cpsr", cond, rd),
+        InstructionType::MoveToStatus => {
+            let fields : String = ["c", "x", "s", "f"].iter().enumerate().filter(|(ind, _)| get_bit(instruction, 16 + *ind as u32)).map(|(_, f)| *f).collect();
+            let source = if get_bit(instruction, 25) {
+                format!("#{}", number(get_bits(instruction, 0, 8).rotate_right(get_bits(instruction, 8, 4) * 2)))
+            } else {reg_name(get_bits(instruction, 0, 4)).to_string()};
+            format!("msr{} cpsr_{}, {}", cond, fields, source)
+        },
+        InstructionType::DataProcessing => {
+            let opcode = get_bits(instruction, 21, 4);
+            let operand2 = if get_bit(instruction, 25) {
+                format!("#{}", number(get_bits(instruction, 0, 8).rotate_right(get_bits(instruction, 8, 4) * 2)))
+            } else {shifted_register(instruction)};
+            match opcode {
+                /* tst, teq, cmp, cmn always set flags */
+                8..=11 => format!("{}{} {}, {}", OPCODES[opcode as usize], cond, rn, operand2),
+                _ => {
+                    let s = if get_bit(instruction, 20) {"s"} else {""};
+                    match opcode {
+                        13 | 15 => format!("{}{}{} {}, {}", OPCODES[opcode as usize], cond, s, rd, operand2),
+                        _ => format!("{}{}{} {}, {}, {}", OPCODES[opcode as usize], cond, s, rd, rn, operand2)
+                    }
+                }
+            }
+        },
+        InstructionType::Coprocessor => {
+            let (cp, crd, crm) = (get_bits(instruction, 8, 4), get_bits(instruction, 12, 4), get_bits(instruction, 0, 4));
+            if get_bits(instruction, 25, 3) == 0b110 {
+                let name = if get_bit(instruction, 20) {"ldc"} else {"stc"};
+                let offset = format!("#{}{}", if get_bit(instruction, 23) {""} else {"-"}, number(get_bits(instruction, 0, 8) * 4));
+                format!("{}{} p{}, c{}, {}", name, cond, cp, crd, address(instruction, rn, &offset, get_bits(instruction, 0, 8) == 0))
+            } else if get_bit(instruction, 4) {
+                let name = if get_bit(instruction, 20) {"mrc"} else {"mcr"};
+                format!("{}{} p{}, {}, {}, c{}, c{}, {}", name, cond, cp, get_bits(instruction, 21, 3), rd, get_bits(instruction, 16, 4), crm, get_bits(instruction, 5, 3))
+            } else {
+                format!("cdp{} p{}, {}, c{}, c{}, c{}, {}", cond, cp, get_bits(instruction, 20, 4), crd, get_bits(instruction, 16, 4), crm, get_bits(instruction, 5, 3))
+            }
+        },
+        InstructionType::Unknown => format!(".word {:#010x}", instruction)
+    }
+}
+
+/* Format the address operand of a data transfer
+rn      <-  base register name
+offset  <-  formatted offset
+zero    <-  the offset is an immediate zero and can be omitted */
+fn address(instruction : &u32, rn : &str, offset : &str, zero : bool) -> String {
+    match (get_bit(instruction, 24), zero) {
+        (true, true) => format!("[{}]", rn),
+        (true, false) => format!("[{}, {}]{}", rn, offset, if get_bit(instruction, 21) {"!"} else {""}),
+        (false, _) => format!("[{}], {}", rn, offset)
+    }
+}
+
+/* Format a register operand shifted by a constant or a register (bits 0-11) */
+fn shifted_register(instruction : &u32) -> String {
+    let rm = reg_name(get_bits(instruction, 0, 4));
+    let shift = SHIFTS[get_bits(instruction, 5, 2) as usize];
+    if get_bit(instruction, 4) {
+        format!("{}, {} {}", rm, shift, reg_name(get_bits(instruction, 8, 4)))
+    } else {
+        match (shift, get_bits(instruction, 7, 5)) {
+            ("lsl", 0) => rm.to_string(),
+            ("ror", 0) => format!("{}, rrx", rm),
+            (shift, 0) => format!("{}, {} #32", rm, shift),
+            (shift, amount) => format!("{}, {} #{}", rm, shift, amount)
+        }
+    }
+}
+
+/* Format a constant, using hexadecimal for values above 255 */
+fn number(value : u32) -> String {
+    if value > 0xFF {format!("{:#x}", value)} else {value.to_string()}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_encodings() {
+        assert_eq!(disassemble_word(0xE281_0004), "add r0, r1, #4");
+        assert_eq!(disassemble_word(0xE593_2000), "ldr r2, [r3]");
+        assert_eq!(disassemble_word(0x0A00_0005), "beq 0x1c");
+        assert_eq!(disassemble_word(0xE350_0C01), "cmp r0, #0x100");
+        assert_eq!(disassemble_word(0xE000_0291), "mul r0, r1, r2");
+    }
+
+    #[test]
+    fn condition_suffix_and_s_flag() {
+        assert_eq!(disassemble_word(0xE091_0002), "adds r0, r1, r2");
+        assert_eq!(disassemble_word(0x11A0_0101), "movne r0, r1, lsl #2");
+        assert_eq!(disassemble_word(0xB051_0002), "sublts r0, r1, r2");
+    }
+
+    #[test]
+    fn range_resolves_branch_targets() {
+        let mut cpu = CPU::with_memory(0x100);
+        /* mov r0, #1; b 0x0 */
+        cpu.poke_words(&[(0x0, 0xE3A0_0001), (0x4, 0xEAFF_FFFD)]).unwrap();
+        assert_eq!(cpu.disassemble_range(0, 8), vec!["mov r0, #1", "b 0x0"]);
+    }
+
+    #[test]
+    fn range_annotates_peripheral_literals() {
+        let mut cpu = CPU::with_memory(0x100);
+        /* ldr r0, [pc, #0] loading the GPIO SET address, then an ordinary literal */
+        cpu.poke_words(&[(0x0, 0xE59F_0000), (0x4, 0xE59F_1000), (0x8, 0x2020_001C), (0xC, 0x100)]).unwrap();
+        assert_eq!(cpu.disassemble_range(0, 8), vec!["ldr r0, [pc]  ; GPIO SET", "ldr r1, [pc]"]);
+    }
+}
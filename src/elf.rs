@@ -0,0 +1,68 @@
+use std::convert::TryInto;
+
+use crate::LoadError;
+
+// NAMED CONSTANTS============================================================
+/* identification bytes: magic, 32 bit class, little endian data */
+const ELF_MAGIC : [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELF_CLASS_32 : u8 = 1;
+const ELF_DATA_LSB : u8 = 1;
+
+/* e_machine value for ARM */
+const ELF_MACHINE_ARM : u16 = 40;
+
+/* p_type of a loadable segment */
+const PT_LOAD : u32 = 1;
+
+/* size of the ELF32 file header and of a program header */
+const ELF_HEADER_SIZE : usize = 52;
+const PROGRAM_HEADER_SIZE : usize = 32;
+
+// ELF PARSING==================================================================
+/* A loadable segment: data is copied to addr, and the remaining memsize - data.len() bytes are zeroed */
+pub(crate) struct Segment<'a> {
+    pub addr : usize,
+    pub data : &'a [u8],
+    pub memsize : usize
+}
+
+/* Check for the ELF magic number at the start of a file
+bytes   <-  the file contents */
+pub(crate) fn is_elf(bytes : &[u8]) -> bool {bytes.starts_with(&ELF_MAGIC)}
+
+/* Parse a 32 bit little endian ARM ELF executable
+bytes   <-  the file contents
+return  <-  the entry point and PT_LOAD segments, or InvalidElf if the headers are malformed */
+pub(crate) fn parse(bytes : &[u8]) -> Result<(u32, Vec<Segment<'_>>), LoadError> {
+    if bytes.len() < ELF_HEADER_SIZE {return Err(LoadError::InvalidElf("truncated header"))}
+    if bytes[4] != ELF_CLASS_32 || bytes[5] != ELF_DATA_LSB {return Err(LoadError::InvalidElf("not a 32 bit little endian file"))}
+    if half(bytes, 0x12)? != ELF_MACHINE_ARM {return Err(LoadError::InvalidElf("not an ARM executable"))}
+
+    let entry = word(bytes, 0x18)?;
+    let phoff = word(bytes, 0x1C)? as usize;
+    let phentsize = half(bytes, 0x2A)? as usize;
+    let phnum = half(bytes, 0x2C)? as usize;
+    if phnum > 0 && phentsize < PROGRAM_HEADER_SIZE {return Err(LoadError::InvalidElf("program headers too small"))}
+
+    let mut segments = Vec::new();
+    for header in (0..phnum).map(|ind| phoff.saturating_add(ind * phentsize)) {
+        if word(bytes, header)? != PT_LOAD {continue}
+        let offset = word(bytes, header + 4)? as usize;
+        let filesize = word(bytes, header + 16)? as usize;
+        let data = bytes.get(offset..offset.saturating_add(filesize)).ok_or(LoadError::InvalidElf("segment data outside the file"))?;
+        segments.push(Segment {addr : word(bytes, header + 8)? as usize, data, memsize : (word(bytes, header + 20)? as usize).max(filesize)});
+    }
+    Ok((entry, segments))
+}
+
+/* Read a little endian word from the file
+offset  <-  position of the word */
+fn word(bytes : &[u8], offset : usize) -> Result<u32, LoadError> {
+    bytes.get(offset..offset.saturating_add(4)).map(|b| u32::from_le_bytes(b.try_into().unwrap())).ok_or(LoadError::InvalidElf("truncated header"))
+}
+
+/* Read a little endian halfword from the file
+offset  <-  position of the halfword */
+fn half(bytes : &[u8], offset : usize) -> Result<u16, LoadError> {
+    bytes.get(offset..offset.saturating_add(2)).map(|b| u16::from_le_bytes(b.try_into().unwrap())).ok_or(LoadError::InvalidElf("truncated header"))
+}
@@ -0,0 +1,159 @@
+use std::{io::{self, BufRead, BufReader, Write}, net::{TcpListener, ToSocketAddrs}};
+
+use crate::{CPU, StepResult, RuntimeError, PC};
+
+// NAMED CONSTANTS============================================================
+/* signals reported in stop replies */
+const SIGILL : u8 = 4;
+const SIGTRAP : u8 = 5;
+const SIGSEGV : u8 = 11;
+
+/* gdb's default ARM register layout: r0-r15, 8 FPA registers of 12 bytes, fps, then the cpsr */
+const FPA_BYTES : usize = 8 * 12 + 4;
+
+// REMOTE SERIAL PROTOCOL=======================================================
+/* Listen on addr and serve a single gdb connection, e.g. `target remote localhost:1234` from arm-none-eabi-gdb.
+Execution starts from the program's entry point.
+cpu     <-  the machine to debug, with a program loaded
+addr    <-  address to listen on */
+pub fn serve<A : ToSocketAddrs>(cpu : &mut CPU, addr : A) -> io::Result<()> {
+    let (mut stream, _) = TcpListener::bind(addr)?.accept()?;
+    cpu.write_register(PC, cpu.entry);
+    handle_connection(cpu, &mut BufReader::new(stream.try_clone()?), &mut stream)
+}
+
+/* Answer packets from a connected debugger until it detaches, kills the target or closes the connection
+cpu     <-  the machine to debug
+reader  <-  packets from the debugger
+stream  <-  where replies are sent */
+pub fn handle_connection<R : BufRead, W : Write>(cpu : &mut CPU, reader : &mut R, stream : &mut W) -> io::Result<()> {
+    while let Some(packet) = read_packet(reader)? {
+        stream.write_all(b"+")?;
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => stop_reply(SIGTRAP),
+            Some(b'g') => read_registers(cpu),
+            Some(b'G') => write_registers(cpu, &packet[1..]),
+            Some(b'm') => read_memory(cpu, &packet[1..]),
+            Some(b's') => stopped(cpu.step()),
+            Some(b'c') => stopped(cpu.resume()),
+            Some(b'Z') | Some(b'z') => breakpoint(cpu, &packet),
+            Some(b'D') => {
+                write_packet(stream, "OK")?;
+                return Ok(());
+            },
+            Some(b'k') => return Ok(()),
+            /* an empty reply marks the packet as unsupported */
+            _ => String::new()
+        };
+        write_packet(stream, &reply)?;
+    }
+    Ok(())
+}
+
+/* Read the next packet ($data#checksum), skipping acknowledgements and interrupts
+return  <-  the packet data, or None once the connection is closed */
+fn read_packet<R : BufRead>(reader : &mut R) -> io::Result<Option<String>> {
+    let mut data = Vec::new();
+    reader.read_until(b'$', &mut data)?;
+    if data.pop() != Some(b'$') {return Ok(None)}
+    data.clear();
+    reader.read_until(b'#', &mut data)?;
+    if data.pop() != Some(b'#') {return Ok(None)}
+    /* the checksum is not verified, TCP already guarantees delivery */
+    reader.read_exact(&mut [0; 2])?;
+    Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+}
+
+/* Send a packet, appending its checksum
+data    <-  the packet contents */
+fn write_packet<S : Write>(stream : &mut S, data : &str) -> io::Result<()> {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    write!(stream, "${}#{:02x}", data, checksum)?;
+    stream.flush()
+}
+
+// PACKET HANDLERS==============================================================
+/* Format a stop reply for a signal */
+fn stop_reply(signal : u8) -> String {format!("S{:02x}", signal)}
+
+/* Report why execution stopped after a step or continue
+result  <-  the outcome of CPU::step or CPU::resume */
+fn stopped(result : Result<StepResult, RuntimeError>) -> String {
+    match result {
+        /* the program exited with status 0 */
+        Ok(StepResult::Halted) => String::from("W00"),
+        Ok(_) => stop_reply(SIGTRAP),
+        Err(RuntimeError::OutOfBounds {..}) | Err(RuntimeError::UnalignedAccess {..}) | Err(RuntimeError::WriteToReadOnly {..}) => stop_reply(SIGSEGV),
+        Err(_) => stop_reply(SIGILL)
+    }
+}
+
+/* Encode every register as little endian hex, the PC is reported as the address of the next instruction
+rather than the fetch address running ahead in the pipeline */
+fn read_registers(cpu : &CPU) -> String {
+    let mut registers = cpu.registers;
+    registers[PC] = cpu.next_address();
+    let mut reply : String = registers.iter().map(|reg| hex_word(*reg)).collect();
+    reply.push_str(&"0".repeat(FPA_BYTES * 2));
+    reply.push_str(&hex_word(cpu.get_cpsr()));
+    reply
+}
+
+/* Set registers from a G packet in the layout of read_registers, the FPA registers are ignored
+data    <-  the packet after the 'G' */
+fn write_registers(cpu : &mut CPU, data : &str) -> String {
+    let words : Option<Vec<u32>> = (0..16).chain(Some(16 + FPA_BYTES / 4))
+        .map(|ind| data.get(ind * 8..ind * 8 + 8).and_then(parse_word))
+        .collect();
+    match words {
+        Some(words) => {
+            /* setting the PC refills the pipeline from the new address */
+            for (reg, val) in words[..16].iter().enumerate() {cpu.write_register(reg, *val)}
+            cpu.set_cpsr(words[16]);
+            String::from("OK")
+        },
+        None => String::from("E01")
+    }
+}
+
+/* Read memory for an m packet (addr,length in hex)
+data    <-  the packet after the 'm' */
+fn read_memory(cpu : &CPU, data : &str) -> String {
+    let range = split_pair(data).and_then(|(addr, len)| Some(addr..addr.checked_add(len)?));
+    match range.and_then(|range| cpu.memory.get(range)) {
+        Some(bytes) => bytes.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        None => String::from("E01")
+    }
+}
+
+/* Insert (Z0) or remove (z0) a software breakpoint, other breakpoint types are unsupported
+packet  <-  the whole packet, e.g. "Z0,1c,4" */
+fn breakpoint(cpu : &mut CPU, packet : &str) -> String {
+    let addr = match packet.get(1..3) {
+        Some("0,") => packet[3..].split(',').next().and_then(|addr| u32::from_str_radix(addr, 16).ok()),
+        _ => return String::new()
+    };
+    match addr {
+        Some(addr) if packet.starts_with('Z') => {
+            cpu.add_breakpoint(addr);
+            String::from("OK")
+        },
+        Some(addr) => {
+            cpu.remove_breakpoint(addr);
+            String::from("OK")
+        },
+        None => String::from("E01")
+    }
+}
+
+/* Format a word as 8 hex digits in target (little endian) byte order */
+fn hex_word(val : u32) -> String {val.to_le_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()}
+
+/* Parse 8 hex digits in target (little endian) byte order */
+fn parse_word(hex : &str) -> Option<u32> {u32::from_str_radix(hex, 16).ok().map(u32::swap_bytes)}
+
+/* Parse a pair of hex numbers separated by a comma, e.g. "100,4" */
+fn split_pair(data : &str) -> Option<(usize, usize)> {
+    let (first, second) = data.split_once(',')?;
+    Some((usize::from_str_radix(first, 16).ok()?, usize::from_str_radix(second, 16).ok()?))
+}
@@ -0,0 +1,57 @@
+use crate::LoadError;
+
+// NAMED CONSTANTS============================================================
+/* record types */
+const DATA : u8 = 0;
+const END_OF_FILE : u8 = 1;
+const EXTENDED_SEGMENT_ADDRESS : u8 = 2;
+const START_SEGMENT_ADDRESS : u8 = 3;
+const EXTENDED_LINEAR_ADDRESS : u8 = 4;
+const START_LINEAR_ADDRESS : u8 = 5;
+
+// INTEL HEX PARSING============================================================
+/* The bytes of a data record and the address they are loaded at */
+pub(crate) struct Record {
+    pub addr : usize,
+    pub data : Vec<u8>
+}
+
+/* Check for the start code of the first record
+bytes   <-  the file contents */
+pub(crate) fn is_ihex(bytes : &[u8]) -> bool {bytes.starts_with(b":")}
+
+/* Parse Intel HEX records up to the end of file record, anything after it is ignored
+text    <-  the file contents, one record per line
+return  <-  the start address (if given) and the data records, or
+            InvalidHex with the line of the first malformed record */
+pub(crate) fn parse(text : &str) -> Result<(Option<u32>, Vec<Record>), LoadError> {
+    let mut base = 0;
+    let mut start = None;
+    let mut records = Vec::new();
+
+    for (ind, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let invalid = |reason| LoadError::InvalidHex {line : ind + 1, reason};
+        let hex = line.trim().strip_prefix(':').ok_or(invalid("missing start code"))?;
+        let bytes = (0..hex.len()).step_by(2)
+            .map(|pos| hex.get(pos..pos + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<Vec<u8>>>().ok_or(invalid("invalid hex digits"))?;
+
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {return Err(invalid("record length does not match its byte count"))}
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {return Err(invalid("checksum mismatch"))}
+
+        let offset = (bytes[1] as usize) << 8 | bytes[2] as usize;
+        let data = &bytes[4..bytes.len() - 1];
+        let value = || data.iter().fold(0u32, |acc, byte| acc << 8 | *byte as u32);
+        match (bytes[3], data.len()) {
+            (DATA, _) => records.push(Record {addr : base + offset, data : data.to_vec()}),
+            (END_OF_FILE, _) => break,
+            (EXTENDED_SEGMENT_ADDRESS, 2) => base = (value() as usize) << 4,
+            (EXTENDED_LINEAR_ADDRESS, 2) => base = (value() as usize) << 16,
+            /* CS:IP is converted to a flat address */
+            (START_SEGMENT_ADDRESS, 4) => start = Some((value() >> 16 << 4).wrapping_add(value() & 0xFFFF)),
+            (START_LINEAR_ADDRESS, 4) => start = Some(value()),
+            _ => return Err(invalid("unsupported record"))
+        }
+    }
+    Ok((start, records))
+}
@@ -0,0 +1,2054 @@
+pub mod asm;
+pub mod decode;
+pub mod disasm;
+mod elf;
+mod ihex;
+pub mod repl;
+mod thumb;
+#[cfg(feature = "gdb")]
+pub mod gdb;
+
+use std::{collections::{HashMap, HashSet}, convert::{TryFrom, TryInto}, ops::Range, fs::{File, write}, fmt, io::{self, Read, Write}, time::{Duration, Instant}};
+use log::info;
+use thumb::ThumbOp;
+
+// NAMED CONSTANTS============================================================
+/* condition codes */
+const EQ : u32 = 0;
+const NE : u32 = 1;
+const CS : u32 = 2;
+const CC : u32 = 3;
+const MI : u32 = 4;
+const PL : u32 = 5;
+const VS : u32 = 6;
+const VC : u32 = 7;
+const HI : u32 = 8;
+const LS : u32 = 9;
+const GE : u32 = 10;
+const LT : u32 = 11;
+const GT : u32 = 12;
+const LE : u32 = 13;
+const AL : u32 = 14;
+
+/* opcodes */
+const AND : u32 = 0;
+const EOR : u32 = 1;
+const SUB : u32 = 2;
+const RSB : u32 = 3;
+const ADD : u32 = 4;
+const ADC : u32 = 5;
+const SBC : u32 = 6;
+const RSC : u32 = 7;
+const TST : u32 = 8;
+const TEQ : u32 = 9;
+const CMP : u32 = 10;
+const CMN : u32 = 11;
+const ORR : u32 = 12;
+const MOV : u32 = 13;
+const BIC : u32 = 14;
+const MVN : u32 = 15;
+
+/* software interrupt numbers */
+const SWI_WRITE_CHAR : u32 = 0;
+const SWI_HALT : u32 = 1;
+
+/* the halt instruction (swi 1), the preferred way for a program to end. A zero word also halts unless
+disabled with set_halt_on_zero, for programs written for the original emulator */
+pub const HALT : u32 = asm::encode_swi(AL, SWI_HALT);
+
+/* register alias */
+pub const SP : usize = 13;
+pub const LR : usize = 14;
+pub const PC : usize = 15;
+
+/* instruction timings (cycles) */
+const DATA_PROCESSING_CYCLES : u64 = 1;
+const REGISTER_SHIFT_CYCLES : u64 = 1;
+const BRANCH_CYCLES : u64 = 3;
+const LOAD_CYCLES : u64 = 3;
+const STORE_CYCLES : u64 = 2;
+const BLOCK_LOAD_CYCLES : u64 = 2;
+const BLOCK_STORE_CYCLES : u64 = 1;
+const MULTIPLY_CYCLES : u64 = 1;
+const ACCUMULATE_CYCLES : u64 = 1;
+const LONG_MULTIPLY_CYCLES : u64 = 2;
+const SOFTWARE_INTERRUPT_CYCLES : u64 = 3;
+const SKIPPED_CYCLES : u64 = 1;
+
+/* sticky saturation flag of the CPSR */
+const Q_BIT : u32 = 27;
+
+/* Thumb state bit of the CPSR and the size of a Thumb instruction */
+const THUMB_BIT : u32 = 5;
+const THUMB_INSTRUCTION_SIZE : u32 = 2;
+
+/* GPIO registers (function select 0-5, set 0-1, clear 0-1) */
+const GPIO_SELECT : u32 = 0x20200000;
+const GPIO_SELECT_END : u32 = 0x20200014;
+const GPIO_SET : u32 = 0x2020001C;
+const GPIO_CLEAR : u32 = 0x20200028;
+const GPIO_PINS : u8 = 54;
+
+/* UART registers (data, status) and the status value reporting it is ready to transmit */
+const UART_DATA : u32 = 0x20201000;
+const UART_STATUS : u32 = 0x20201004;
+const UART_READY : u32 = 1;
+
+/* default memory size (bytes) */
+pub const MEMSIZE : usize = 0x8000;
+
+// UTILITY FUNCTIONS============================================================
+/* Return a range of bits:
+data    <-  Source string of bits
+start   <-  inclusive start
+n       <-  number of bits */
+fn get_bits(data : &u32, start : u32, n : u32) -> u32 {(data >> start) & ((1 << n) - 1)}
+
+/* get bit at Location in a Word:
+data    <-  the Word you are inspecting
+n       <-  bit number (0-31) */
+fn get_bit(data : &u32, n : u32) -> bool {(*data >> n) & 1 != 0}
+
+/* Get the name of a register as used in assembly (r0-r12, sp, lr, pc)
+n       <-  register number (0-15), any other number is named "r?" */
+pub fn reg_name(n : u32) -> &'static str {
+    ["r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12", "sp", "lr", "pc"].get(n as usize).copied().unwrap_or("r?")
+}
+
+/* Parse a register name, case insensitive (r0-r15, or the aliases sp, lr and pc)
+name    <-  the register name
+return  <-  the register number, or None if name is not a register */
+pub fn parse_reg(name : &str) -> Option<u32> {
+    match name.to_lowercase().as_str() {
+        "sp" => Some(SP as u32),
+        "lr" => Some(LR as u32),
+        "pc" => Some(PC as u32),
+        reg => reg.strip_prefix('r').and_then(|n| n.parse().ok()).filter(|n| *n < 16)
+    }
+}
+
+/* Get the number of extra cycles the multiplier takes, terminating early when the top bits of Rs are all 0 or all 1
+rs      <-  value of the Rs operand */
+fn multiply_cycles(rs : u32) -> u64 {
+    if rs >> 8 == 0 || rs >> 8 == 0xFF_FFFF {1}
+    else if rs >> 16 == 0 || rs >> 16 == 0xFFFF {2}
+    else if rs >> 24 == 0 || rs >> 24 == 0xFF {3}
+    else {4}
+}
+
+/* Pack CPSR flags and the Thumb state into a status register word (see CPU::get_cpsr) */
+fn pack_cpsr(cpsr : &Cpsr, thumb : bool) -> u32 {
+    (cpsr.n as u32) << 31 | (cpsr.z as u32) << 30 | (cpsr.c as u32) << 29 | (cpsr.v as u32) << 28
+        | (cpsr.q as u32) << Q_BIT | (thumb as u32) << THUMB_BIT
+}
+
+/* Compare two machine states
+registers, cpsr, memory <-  (before, after) pairs, memory is compared as little endian words
+return                  <-  the values that differ */
+fn diff_state(registers : (&[u32; 16], &[u32; 16]), cpsr : (u32, u32), memory : (&[u8], &[u8])) -> StateDiff {
+    StateDiff {
+        registers : (0..16).filter(|reg| registers.0[*reg] != registers.1[*reg]).map(|reg| (reg, registers.0[reg], registers.1[reg])).collect(),
+        cpsr : Some(cpsr).filter(|(before, after)| before != after),
+        memory : memory.0.chunks_exact(4).zip(memory.1.chunks_exact(4)).enumerate()
+            .filter(|(_, (before, after))| before != after)
+            .map(|(ind, (before, after))| (ind * 4, u32::from_le_bytes([before[0], before[1], before[2], before[3]]), u32::from_le_bytes([after[0], after[1], after[2], after[3]])))
+            .collect()
+    }
+}
+
+/* Name a peripheral register for traces and disassembly
+addr    <-  the address accessed
+return  <-  the register's name, e.g. "GPIO SET", or None for ordinary memory */
+pub fn special_address_name(addr : u32) -> Option<&'static str> {
+    const BANKS : [&str; 6] = ["GPIO pin bank 0", "GPIO pin bank 1", "GPIO pin bank 2", "GPIO pin bank 3", "GPIO pin bank 4", "GPIO pin bank 5"];
+    match addr {
+        GPIO_SELECT..=GPIO_SELECT_END if addr.is_multiple_of(4) => Some(BANKS[((addr - GPIO_SELECT) / 4) as usize]),
+        GPIO_SET => Some("GPIO SET"),
+        a if a == GPIO_SET + 4 => Some("GPIO SET (pins 32-53)"),
+        GPIO_CLEAR => Some("GPIO CLEAR"),
+        a if a == GPIO_CLEAR + 4 => Some("GPIO CLEAR (pins 32-53)"),
+        UART_DATA => Some("UART DATA"),
+        UART_STATUS => Some("UART STATUS"),
+        _ => None
+    }
+}
+
+/* Check the endian-ness of the system the emulator is being run on
+return  <-  True (little endian), False (big endian) */
+pub fn endian_check() -> bool {1u32.to_ne_bytes()[0] == 1}
+
+/* Classify an instruction by its fixed bits, checked in an order that resolves overlapping encodings
+instruction <-  the instruction to classify */
+fn instruction_type(instruction : &u32) -> InstructionType {
+    if get_bits(instruction, 24, 4) == 0b1111 {
+        InstructionType::SoftwareInterrupt
+    } else if get_bits(instruction, 25, 3) == 0b101 {
+        InstructionType::Branch
+    } else if get_bits(instruction, 25, 3) == 0b100 {
+        InstructionType::BlockDataTransfer
+    } else if get_bits(instruction, 26, 2) == 0b01 && !(get_bit(instruction, 25) && get_bit(instruction, 4)) {
+        InstructionType::SingleDataTransfer
+    } else if get_bits(instruction, 22, 6) == 0 && get_bits(instruction, 4, 4) == 0b1001 {
+        InstructionType::Multiply
+    } else if get_bits(instruction, 23, 5) == 0b00001 && get_bits(instruction, 4, 4) == 0b1001 {
+        InstructionType::LongMultiply
+    } else if get_bits(instruction, 25, 3) == 0 && get_bit(instruction, 7) && get_bit(instruction, 4) && get_bits(instruction, 5, 2) != 0 {
+        InstructionType::HalfwordDataTransfer
+    } else if *instruction & 0x0FFF_FFF0 == 0x012F_FF10 {
+        InstructionType::BranchExchange
+    } else if *instruction & 0x0F90_0FF0 == 0x0100_0050 {
+        InstructionType::SaturatingArithmetic
+    } else if *instruction & 0x0FFF_0FF0 == 0x016F_0F10 {
+        InstructionType::CountLeadingZeros
+    } else if *instruction & 0x0FBF_0FFF == 0x010F_0000 {
+        InstructionType::MoveFromStatus
+    } else if *instruction & 0x0DB0_F000 == 0x0120_F000 && (get_bit(instruction, 25) || get_bits(instruction, 4, 8) == 0) {
+        InstructionType::MoveToStatus
+    } else if get_bits(instruction, 26, 2) == 0 {
+        InstructionType::DataProcessing
+    } else if get_bits(instruction, 25, 3) == 0b110 || get_bits(instruction, 24, 4) == 0b1110 {
+        InstructionType::Coprocessor
+    } else {
+        InstructionType::Unknown
+    }
+}
+
+/* Describe the bit fields instruction_type classifies on, and why an unrecognised instruction matched no class.
+Every other pattern of bits 27-25 belongs to a class, so an unrecognised instruction has 011 there with bit 4 set.
+instruction <-  the unrecognised instruction */
+fn classification_fields(instruction : &u32) -> String {
+    format!(
+        "cond {:04b}, bits 27-25 {:03b}, bits 24-20 {:05b}, bits 7-4 {:04b}: bits 27-25 are 011 with bit 4 set \
+        (the undefined instruction space, a single data transfer cannot shift its offset by a register)",
+        get_bits(instruction, 28, 4), get_bits(instruction, 25, 3), get_bits(instruction, 20, 5), get_bits(instruction, 4, 4)
+    )
+}
+
+// MACHINE STATE STRUCTS========================================================
+/* Instruction classes recognised by the decoder */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InstructionType {
+    SoftwareInterrupt,
+    Branch,
+    BlockDataTransfer,
+    SingleDataTransfer,
+    Multiply,
+    LongMultiply,
+    HalfwordDataTransfer,
+    BranchExchange,
+    SaturatingArithmetic,
+    CountLeadingZeros,
+    MoveFromStatus,
+    MoveToStatus,
+    DataProcessing,
+    Coprocessor,
+    Unknown
+}
+
+/* A register number, checked to be in 0-15 when constructed with Register::try_from */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Register(u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cpsr {
+    pub n : bool,
+    pub z : bool,
+    pub c : bool,
+    pub v : bool,
+    /* set when a saturating instruction clamps its result, only cleared by writing the CPSR */
+    pub q : bool
+}
+
+/* A memory mapped peripheral, loads and stores to addresses in its range are routed to it instead of RAM.
+Transfers are always whole words, the byte bit of a data transfer is ignored for devices. */
+pub trait MmioDevice {
+    /* Load a word from a device register
+    addr    <-  the address accessed, within range() */
+    fn read(&mut self, addr : u32) -> u32;
+
+    /* Store a word to a device register
+    addr    <-  the address accessed, within range()
+    val     <-  the value written */
+    fn write(&mut self, addr : u32, val : u32);
+
+    /* The addresses the device responds to */
+    fn range(&self) -> Range<u32>;
+}
+
+/* GPIO controller, tracking the level of pins 0-53 */
+pub struct GpioDevice {
+    pins : u64,
+    pub trace : bool
+}
+
+/* UART transmitter, storing to the data register writes the low byte to the output */
+pub struct UartDevice<W : Write> {
+    output : W
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct CPU {
+    pub registers : [u32; 16],
+    pub cpsr : Cpsr,
+    /* saved status register, restored by data processing instructions writing the PC with the S bit set.
+    Exception modes are not modelled, so it is only present once set_spsr is used */
+    spsr : Option<Cpsr>,
+    pub memory : Vec<u8>,
+    /* word filling memory that has not been loaded or stored to, zero unless created with with_poison */
+    poison : Option<u32>,
+    /* byte order of word and halfword data accesses, set with set_endian */
+    endian : Endian,
+    pub gpio : GpioDevice,
+    devices : Vec<Box<dyn MmioDevice>>,
+    pipeline : Pipeline,
+    thumb : bool,
+    entry : u32,
+    breakpoints : HashSet<u32>,
+    watchpoints : HashMap<usize, WatchKind>,
+    rom : Option<Range<usize>>,
+    stack_pointer : Option<u32>,
+    record_writes : bool,
+    write_log : Vec<(u64, usize, u32)>,
+    watch_hit : Option<(usize, WatchKind)>,
+    /* whether each halfword address has been executed, for coverage */
+    executed : Vec<bool>,
+    cycles : u64,
+    trace : bool,
+    /* destination of trace lines, stderr unless set with set_trace_output */
+    trace_output : Box<dyn Write>,
+    /* destination of characters written by SWI 0, stdout unless set with set_output */
+    output : Box<dyn Write>,
+    halted : bool,
+    halt_on_zero : bool,
+    code_end : Option<u32>,
+    skip_coprocessor : bool,
+    instructions : u64,
+    max_instructions : Option<u64>
+}
+
+/* Instructions in flight in the three stage pipeline, each held with the address it was fetched from.
+A fetch that fails only raises its error once the instruction reaches the execute stage. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Pipeline {
+    fetched : Option<(u32, Result<u32, RuntimeError>)>,
+    decoded : Option<(u32, Result<u32, RuntimeError>)>
+}
+
+/* Kind of memory access a watchpoint stops on (Access stops on both reads and writes) */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access
+}
+
+/* Byte order of data loads and stores (instructions are always fetched little endian) */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big
+}
+
+/* Outcome of executing a single instruction */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepResult {
+    Halted,
+    Branched,
+    Continued,
+    Breakpoint(u32),
+    Watchpoint {addr : usize, kind : WatchKind}
+}
+
+/* Timing of a run_benchmark run */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchStats {
+    pub elapsed : Duration,
+    pub instructions : u64,
+    pub instructions_per_second : f64
+}
+
+/* The execution state of a CPU at one point, created by CPU::checkpoint and returned to with CPU::restore.
+Configuration (breakpoints, devices, limits) is not part of it. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuSnapshot {
+    registers : [u32; 16],
+    cpsr : Cpsr,
+    spsr : Option<Cpsr>,
+    thumb : bool,
+    memory : Vec<u8>,
+    pipeline : Pipeline,
+    gpio_pins : u64,
+    watch_hit : Option<(usize, WatchKind)>,
+    cycles : u64,
+    instructions : u64,
+    halted : bool
+}
+
+impl CpuSnapshot {
+    /* Compare with a later snapshot of the same CPU (see CPU::diff) */
+    pub fn diff(&self, other : &CpuSnapshot) -> StateDiff {
+        diff_state((&self.registers, &other.registers), (pack_cpsr(&self.cpsr, self.thumb), pack_cpsr(&other.cpsr, other.thumb)), (&self.memory, &other.memory))
+    }
+}
+
+/* The differences between two machine states, each as (before, after) values. The PC is compared as the
+register value, so it differs after any instruction. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    /* (register number, before, after) */
+    pub registers : Vec<(usize, u32, u32)>,
+    /* the packed status register, if it differs */
+    pub cpsr : Option<(u32, u32)>,
+    /* (word address, before, after) */
+    pub memory : Vec<(usize, u32, u32)>
+}
+
+impl StateDiff {
+    /* Check whether the states were identical */
+    pub fn is_empty(&self) -> bool {self.registers.is_empty() && self.cpsr.is_none() && self.memory.is_empty()}
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        for (reg, before, after) in &self.registers {writeln!(f, "{}: {:#010x} -> {:#010x}", reg_name(*reg as u32), before, after)?}
+        if let Some((before, after)) = self.cpsr {writeln!(f, "cpsr: {:#010x} -> {:#010x}", before, after)?}
+        for (loc, before, after) in &self.memory {writeln!(f, "{:#010x}: {:#010x} -> {:#010x}", loc, before, after)?}
+        Ok(())
+    }
+}
+
+// ERROR TYPES==================================================================
+/* Error produced when converting a number outside 0-15 into a Register */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidRegister(pub u32);
+
+impl fmt::Display for InvalidRegister {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {write!(f, "Invalid register number: {}", self.0)}
+}
+
+impl std::error::Error for InvalidRegister {}
+
+/* Errors raised while executing a program */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+    InvalidInstruction {instruction : u32, reason : &'static str},
+    /* a register combination the architecture leaves unpredictable */
+    InvalidEncoding {instruction : u32, reason : &'static str},
+    UnknownInstruction {instruction : u32},
+    OutOfBounds {addr : usize},
+    UnalignedAccess {addr : usize},
+    WriteToReadOnly {addr : usize},
+    BranchOutOfRange {target : u32},
+    UnsupportedCoprocessor {cp_num : u32},
+    InstructionLimitExceeded {limit : u64}
+}
+
+impl RuntimeError {
+    /* Create the error for an instruction that cannot be executed
+    instruction <-  the offending instruction
+    reason      <-  description of the problem */
+    fn invalid(instruction : &u32, reason : &'static str) -> RuntimeError {
+        RuntimeError::InvalidInstruction {instruction : *instruction, reason}
+    }
+
+    /* Create the error for an instruction whose effect is unpredictable
+    instruction <-  the offending instruction
+    reason      <-  the conflicting registers */
+    fn unpredictable(instruction : &u32, reason : &'static str) -> RuntimeError {
+        RuntimeError::InvalidEncoding {instruction : *instruction, reason}
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::InvalidInstruction {instruction, reason} => write!(f, "{}: {:#010x}", reason, instruction),
+            RuntimeError::InvalidEncoding {instruction, reason} => write!(f, "Unpredictable encoding, {}: {:#010x}", reason, instruction),
+            RuntimeError::UnknownInstruction {instruction} => write!(f, "Invalid instruction type: {:#010x} ({})", instruction, classification_fields(instruction)),
+            RuntimeError::OutOfBounds {addr} => write!(f, "Out of bounds memory access at address {:#010x}", addr),
+            RuntimeError::UnalignedAccess {addr} => write!(f, "Unaligned word access at address {:#010x}", addr),
+            RuntimeError::WriteToReadOnly {addr} => write!(f, "Store to read only memory at address {:#010x}", addr),
+            RuntimeError::BranchOutOfRange {target} => write!(f, "Branch to {:#010x} is outside memory or unaligned", target),
+            RuntimeError::UnsupportedCoprocessor {cp_num} => write!(f, "Coprocessor p{} is not supported", cp_num),
+            RuntimeError::InstructionLimitExceeded {limit} => write!(f, "Instruction limit of {} exceeded", limit)
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/* Errors produced when loading a binary into memory */
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    TooLarge {size : usize, capacity : usize},
+    InvalidElf(&'static str),
+    InvalidHex {line : usize, reason : &'static str},
+    SegmentOutOfRange {addr : usize, size : usize}
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "Could not read file: {}", err),
+            LoadError::TooLarge {size, capacity} => write!(f, "Binary of {} bytes is too large for {} byte memory", size, capacity),
+            LoadError::InvalidElf(reason) => write!(f, "Invalid ELF file: {}", reason),
+            LoadError::InvalidHex {line, reason} => write!(f, "Invalid Intel HEX file: line {}: {}", line, reason),
+            LoadError::SegmentOutOfRange {addr, size} => write!(f, "Segment of {} bytes at {:#010x} does not fit in memory", size, addr)
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(err : io::Error) -> LoadError {LoadError::Io(err)}
+}
+
+// REGISTER IMPLEMENTATION======================================================
+impl Register {
+    pub const SP : Register = Register(SP as u8);
+    pub const LR : Register = Register(LR as u8);
+    pub const PC : Register = Register(PC as u8);
+
+    /* Get the register number (0-15) */
+    pub fn index(self) -> usize {self.0 as usize}
+}
+
+impl TryFrom<u32> for Register {
+    type Error = InvalidRegister;
+
+    fn try_from(reg : u32) -> Result<Register, InvalidRegister> {
+        if reg < 16 {Ok(Register(reg as u8))} else {Err(InvalidRegister(reg))}
+    }
+}
+
+impl From<Register> for usize {
+    fn from(reg : Register) -> usize {reg.index()}
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {write!(f, "{}", reg_name(self.0 as u32))}
+}
+
+// GPIO IMPLEMENTATION==========================================================
+impl GpioDevice {
+    /* Get the level of a pin (false for pins that do not exist)
+    pin     <-  pin number (0-53) */
+    pub fn pin(&self, pin : u8) -> bool {pin < GPIO_PINS && (self.pins >> pin) & 1 != 0}
+
+    /* Log the access (at info level) when tracing is enabled */
+    fn trace_access(&self, addr : u32) {
+        if !self.trace {return}
+        match addr {
+            GPIO_SELECT..=GPIO_SELECT_END => {
+                let region = (addr - GPIO_SELECT) / 4 * 10;
+                info!("One GPIO pin from {} to {} has been accessed", region, (region + 9).min(GPIO_PINS as u32 - 1));
+            },
+            GPIO_CLEAR => info!("PIN OFF"),
+            GPIO_SET => info!("PIN ON"),
+            _ => ()
+        }
+    }
+}
+
+impl MmioDevice for GpioDevice {
+    /* Load from a GPIO register, function select registers read back their own address */
+    fn read(&mut self, addr : u32) -> u32 {
+        self.trace_access(addr);
+        if addr <= GPIO_SELECT_END {addr} else {0}
+    }
+
+    /* Store to a GPIO register, set and clear registers turn on/off each pin with a 1 bit in val */
+    fn write(&mut self, addr : u32, val : u32) {
+        self.trace_access(addr);
+        match addr {
+            GPIO_SET => self.pins |= val as u64,
+            a if a == GPIO_SET + 4 => self.pins |= (val as u64) << 32,
+            GPIO_CLEAR => self.pins &= !(val as u64),
+            a if a == GPIO_CLEAR + 4 => self.pins &= !((val as u64) << 32),
+            _ => ()
+        }
+        self.pins &= (1 << GPIO_PINS) - 1;
+    }
+
+    fn range(&self) -> Range<u32> {GPIO_SELECT..GPIO_CLEAR + 8}
+}
+
+// UART IMPLEMENTATION==========================================================
+impl<W : Write> UartDevice<W> {
+    /* Create a UART writing each transmitted character to output
+    output  <-  destination of the characters (e.g. stdout) */
+    pub fn new(output : W) -> UartDevice<W> {UartDevice {output}}
+}
+
+impl<W : Write> MmioDevice for UartDevice<W> {
+    /* The status register always reports ready, the data register reads 0 (there is no receiver) */
+    fn read(&mut self, addr : u32) -> u32 {if addr == UART_STATUS {UART_READY} else {0}}
+
+    fn write(&mut self, addr : u32, val : u32) {
+        if addr == UART_DATA {
+            self.output.write_all(&[val as u8]).and_then(|_| self.output.flush()).ok();
+        }
+    }
+
+    fn range(&self) -> Range<u32> {UART_DATA..UART_STATUS + 4}
+}
+
+impl Default for CPU {
+    fn default() -> CPU {CPU::new()}
+}
+
+// EMULATOR IMPLEMENTATION======================================================
+impl CPU {
+
+    /* Create a new CPU struct:
+    return  <-  New CPU with registers, memory initialised */
+    pub fn new() -> CPU {CPU::with_memory(MEMSIZE)}
+
+    /* Create a new CPU struct with uninitialised memory filled with a pattern instead of zeros (also after a reset),
+    to catch programs relying on zeroed memory. Executing a pattern word halts the machine as a zero word does.
+    pattern <-  the word repeated through memory, e.g. 0xDEADBEEF
+    return  <-  New CPU with MEMSIZE bytes of poisoned memory */
+    pub fn with_poison(pattern : u32) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.poison = Some(pattern);
+        cpu.clear_memory();
+        cpu
+    }
+
+    /* Create a new CPU struct with a given amount of memory:
+    size    <-  memory size (bytes)
+    return  <-  New CPU with registers, memory initialised */
+    pub fn with_memory(size : usize) -> CPU {
+        CPU {
+            registers : [0; 16],
+            cpsr : Cpsr {
+                n : false,
+                z : false,
+                c : false,
+                v : false,
+                q : false
+            },
+            spsr : None,
+            memory : vec![0; size],
+            poison : None,
+            endian : Endian::Little,
+            gpio : GpioDevice {pins : 0, trace : true},
+            devices : vec![Box::new(UartDevice::new(io::stdout()))],
+            pipeline : Pipeline::default(),
+            thumb : false,
+            entry : 0,
+            breakpoints : HashSet::new(),
+            watchpoints : HashMap::new(),
+            rom : None,
+            stack_pointer : None,
+            record_writes : false,
+            write_log : Vec::new(),
+            watch_hit : None,
+            executed : vec![false; size / 2],
+            cycles : 0,
+            trace : false,
+            trace_output : Box::new(io::stderr()),
+            output : Box::new(io::stdout()),
+            halted : false,
+            halt_on_zero : true,
+            code_end : None,
+            skip_coprocessor : false,
+            instructions : 0,
+            max_instructions : None
+        }
+    }
+
+    /* Return the machine to its initial state for another run without reallocating memory: registers,
+    flags, memory, GPIO pins, counters and coverage are zeroed, while configuration (breakpoints, tracing, limits) is kept
+    and the stack pointer is set again if set_stack_pointer was used */
+    pub fn reset(&mut self) {
+        self.registers = [0; 16];
+        self.registers[SP] = self.stack_pointer.unwrap_or(0);
+        self.cpsr = Cpsr {n : false, z : false, c : false, v : false, q : false};
+        self.spsr = None;
+        self.clear_memory();
+        self.gpio.pins = 0;
+        self.pipeline = Pipeline::default();
+        self.thumb = false;
+        self.entry = 0;
+        self.watch_hit = None;
+        self.write_log.clear();
+        self.executed.fill(false);
+        self.cycles = 0;
+        self.instructions = 0;
+        self.halted = false;
+    }
+
+    /* Compare this machine's registers, CPSR and memory with another's, e.g. the same program after stepping
+    other   <-  the state to compare against, memory beyond the smaller of the two is ignored
+    return  <-  the differences as (this, other) values */
+    pub fn diff(&self, other : &CPU) -> StateDiff {
+        diff_state((&self.registers, &other.registers), (self.get_cpsr(), other.get_cpsr()), (&self.memory, &other.memory))
+    }
+
+    /* Capture the registers, flags, memory, pipeline and counters, e.g. to step backwards by restoring an earlier state */
+    pub fn checkpoint(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            registers : self.registers,
+            cpsr : self.cpsr,
+            spsr : self.spsr,
+            thumb : self.thumb,
+            memory : self.memory.clone(),
+            pipeline : self.pipeline.clone(),
+            gpio_pins : self.gpio.pins,
+            watch_hit : self.watch_hit,
+            cycles : self.cycles,
+            instructions : self.instructions,
+            halted : self.halted
+        }
+    }
+
+    /* Return to the state captured by checkpoint, execution continues from the restored pipeline. Output already
+    written by devices cannot be undone.
+    snap    <-  a snapshot taken from this CPU */
+    pub fn restore(&mut self, snap : &CpuSnapshot) {
+        self.registers = snap.registers;
+        self.cpsr = snap.cpsr;
+        self.spsr = snap.spsr;
+        self.thumb = snap.thumb;
+        self.memory.clone_from(&snap.memory);
+        self.pipeline = snap.pipeline.clone();
+        self.gpio.pins = snap.gpio_pins;
+        self.watch_hit = snap.watch_hit;
+        self.cycles = snap.cycles;
+        self.instructions = snap.instructions;
+        self.halted = snap.halted;
+    }
+
+    /* Fill memory with zeros, or the poison pattern if one is set (a partial last word is zeroed) */
+    fn clear_memory(&mut self) {
+        self.memory.fill(0);
+        if let Some(pattern) = self.poison {
+            for word in self.memory.chunks_exact_mut(4) {word.copy_from_slice(&pattern.to_le_bytes())}
+        }
+    }
+
+    /* Choose whether fetching a zero word (or the poison pattern, see with_poison) halts the machine (the default),
+    or executes it as an instruction (andeq r0, r0, r0 for zero) leaving the halt SWI as the only way to stop
+    halt_on_zero <- true to halt on a zero word */
+    pub fn set_halt_on_zero(&mut self, halt_on_zero : bool) {self.halt_on_zero = halt_on_zero}
+
+    /* Halt when execution reaches the end of the code rather than relying on a zero word after it, for images
+    with data straight after their last instruction
+    bytes   <-  length of the code loaded at address 0, instructions at or beyond it are not executed */
+    pub fn set_code_length(&mut self, bytes : usize) {self.code_end = Some(bytes as u32)}
+
+    /* Choose whether coprocessor instructions (CDP, MRC/MCR, LDC/STC) raise UnsupportedCoprocessor (the default)
+    or are skipped as no-ops, noting them in the trace, so programs containing them can still be run
+    skip    <- true to skip coprocessor instructions */
+    pub fn set_skip_coprocessor(&mut self, skip : bool) {self.skip_coprocessor = skip}
+
+    /* Stop run_program/resume with InstructionLimitExceeded once limit instructions have executed (unlimited by default)
+    limit   <-  maximum number of instructions, including those skipped by their condition */
+    pub fn set_instruction_limit(&mut self, limit : u64) {self.max_instructions = Some(limit)}
+
+    /* Set the initial stack pointer (SP starts at 0 unless this is used), e.g. memory.len() for a full descending
+    stack at the top of memory. SP is set immediately and again on every reset
+    addr    <-  initial value of SP */
+    pub fn set_stack_pointer(&mut self, addr : u32) {
+        self.stack_pointer = Some(addr);
+        self.registers[SP] = addr;
+    }
+
+    /* Enable or disable recording every store to memory (device registers are not recorded) in the write log
+    record  <-  true to record stores */
+    pub fn set_record_writes(&mut self, record : bool) {self.record_writes = record}
+
+    /* Take the stores recorded since the log was last taken, oldest first
+    return  <-  (instruction count when stored, address, value) for each store, bytes and halfwords zero extended */
+    pub fn take_write_log(&mut self) -> Vec<(u64, usize, u32)> {std::mem::take(&mut self.write_log)}
+
+    /* Get the number of instructions executed so far */
+    pub fn instruction_count(&self) -> u64 {self.instructions}
+
+    /* Get the number of cycles taken by the instructions executed so far */
+    pub fn cycle_count(&self) -> u64 {self.cycles}
+
+    /* Get the addresses of the instructions executed since the last reset (including those whose condition failed),
+    any instruction missing was never reached
+    return  <-  the addresses in ascending order */
+    pub fn coverage(&self) -> Vec<usize> {
+        self.executed.iter().enumerate().filter(|(_, executed)| **executed).map(|(ind, _)| ind * 2).collect()
+    }
+
+    /* Enable or disable logging of each executed instruction (and the registers it changes) to the trace output
+    trace   <-  true to enable tracing */
+    pub fn set_trace(&mut self, trace : bool) {self.trace = trace}
+
+    /* Send trace lines somewhere other than stderr, e.g. a file or a buffer to inspect
+    output  <-  destination of the trace */
+    pub fn set_trace_output(&mut self, output : Box<dyn Write>) {self.trace_output = output}
+
+    /* Send the characters written by SWI 0 somewhere other than stdout
+    output  <-  destination of the program's output */
+    pub fn set_output(&mut self, output : Box<dyn Write>) {self.output = output}
+
+    /* Mark memory from start up to (not including) end as read only, stores into it raise WriteToReadOnly
+    while loads and program loading are unaffected
+    start   <-  first read only address
+    end     <-  address after the last read only byte */
+    pub fn set_rom_range(&mut self, start : usize, end : usize) {self.rom = Some(start..end)}
+
+    /* Get the level of a GPIO pin
+    pin     <-  pin number (0-53) */
+    pub fn gpio_pin(&self, pin : u8) -> bool {self.gpio.pin(pin)}
+
+    /* Map a peripheral into the address space, devices registered later take priority over earlier ones
+    (including the stdout UART mapped by default) and all registered devices take priority over the GPIO controller and RAM
+    device  <-  the peripheral */
+    pub fn add_device(&mut self, device : Box<dyn MmioDevice>) {self.devices.push(device)}
+
+    /* Find the peripheral mapped at an address, if any
+    addr    <-  the address accessed */
+    fn device_at(&mut self, addr : usize) -> Option<&mut dyn MmioDevice> {
+        let addr = u32::try_from(addr).ok()?;
+        match self.devices.iter().rposition(|device| device.range().contains(&addr)) {
+            Some(ind) => Some(self.devices[ind].as_mut()),
+            None if self.gpio.range().contains(&addr) => Some(&mut self.gpio),
+            None => None
+        }
+    }
+
+    /* Get the value held in a register
+    reg     <-  the register */
+    pub fn get_register(&self, reg : Register) -> u32 {self.registers[reg.index()]}
+
+    /* Set the value held in a register, setting the PC flushes the pipeline so execution continues from val
+    reg     <-  the register
+    val     <-  the value to be written */
+    pub fn set_register(&mut self, reg : Register, val : u32) {self.write_register(reg.index(), val)}
+
+    /* Set a register by number, as set_register does. In the Thumb state bit 0 of a value written to the PC is ignored
+    reg     <-  register number (0-15), from a 4 bit instruction field
+    val     <-  the value to be written */
+    fn write_register(&mut self, reg : usize, val : u32) {
+        self.registers[reg] = val;
+        if reg == PC {
+            if self.thumb {self.registers[PC] &= !1}
+            self.pipeline = Pipeline::default();
+        }
+    }
+
+    /* Set every register at once (e.g. to set up a scenario before stepping), the pipeline is refilled from regs[PC]
+    regs    <-  values for registers 0-15 */
+    pub fn set_registers(&mut self, regs : [u32; 16]) {
+        self.registers = regs;
+        self.pipeline = Pipeline::default();
+    }
+
+    /* Switch the byte order of word and halfword loads and stores at runtime, as setend does. Memory itself is not
+    changed, so a word stored before the switch reads back byte swapped. Instruction fetches stay little endian and
+    endian_check only reports the host.
+    endian  <-  the byte order for following data accesses */
+    pub fn set_endian(&mut self, endian : Endian) {self.endian = endian}
+
+    /* Get the byte order of data accesses */
+    pub fn endian(&self) -> Endian {self.endian}
+
+    /* Write words to memory (e.g. to set up fixture data before running), in order
+    entries <-  (address, value) pairs, addresses word aligned
+    return  <-  the error for the first entry that cannot be stored (OutOfBounds, UnalignedAccess or WriteToReadOnly),
+                the entries before it are kept */
+    pub fn poke_words(&mut self, entries : &[(usize, u32)]) -> Result<(), RuntimeError> {
+        entries.iter().try_for_each(|(loc, val)| self.set_mem_word(*loc, *val))
+    }
+
+    /* Set the CPSR condition flags, the Q flag is unchanged
+    n, z, c, v <- negative, zero, carry and overflow flags */
+    pub fn set_flags(&mut self, n : bool, z : bool, c : bool, v : bool) {self.cpsr = Cpsr {n, z, c, v, q : self.cpsr.q}}
+
+    /* Set (or clear with None) the saved status register restored by movs pc, lr style instructions
+    spsr    <-  the flags to restore */
+    pub fn set_spsr(&mut self, spsr : Option<Cpsr>) {self.spsr = spsr}
+
+    /* Get the saved status register, None if there is none */
+    pub fn get_spsr(&self) -> Option<Cpsr> {self.spsr}
+
+    /* Pack the CPSR flags into a status register word (N, Z, C, V, Q in bits 31-27 and the Thumb state T in bit 5) */
+    pub fn get_cpsr(&self) -> u32 {pack_cpsr(&self.cpsr, self.thumb)}
+
+    /* Unpack a status register word into the CPSR flags and Thumb state, other bits are ignored
+    val     <-  the status register word (N, Z, C, V, Q in bits 31-27, T in bit 5) */
+    pub fn set_cpsr(&mut self, val : u32) {
+        self.cpsr = Cpsr {n : get_bit(&val, 31), z : get_bit(&val, 30), c : get_bit(&val, 29), v : get_bit(&val, 28), q : get_bit(&val, Q_BIT)};
+        self.set_thumb(get_bit(&val, THUMB_BIT));
+    }
+
+    /* Switch between the ARM and Thumb instruction sets, the pipeline is refilled from the next instruction
+    thumb   <-  true to fetch and execute 16 bit Thumb instructions */
+    pub fn set_thumb(&mut self, thumb : bool) {
+        if thumb == self.thumb {return}
+        let next = self.next_address();
+        self.thumb = thumb;
+        self.write_register(PC, next);
+    }
+
+    /* Get the byte at a given memory location
+    loc     <-  location of the byte in memory */
+    fn get_mem_byte(&self, loc : usize) -> u8 {self.memory[loc]}
+
+    /* Set the byte at a given memory location
+    loc     <-  location of the byte in memory
+    val     <-  the value to be written
+    return  <-  WriteToReadOnly if loc is in the ROM range */
+    fn set_mem_byte(&mut self, loc : usize, val : u8) -> Result<(), RuntimeError> {
+        self.check_writable(loc, 1)?;
+        self.log_write(loc, val as u32);
+        self.memory[loc] = val;
+        Ok(())
+    }
+
+    /* Get the halfword at a given memory location in the data byte order
+    loc     <-  location of the start of the 2 bytes in memory */
+    fn get_mem_halfword(&self, loc : usize) -> u16 {
+        let bytes = self.memory[loc..loc+2].try_into().unwrap();
+        match self.endian {
+            Endian::Little => u16::from_le_bytes(bytes),
+            Endian::Big => u16::from_be_bytes(bytes)
+        }
+    }
+
+    /* Set the halfword at a given memory location, stored in the data byte order
+    loc     <-  location of the start of the 2 bytes in memory
+    val     <-  the value to be written
+    return  <-  WriteToReadOnly if either byte is in the ROM range */
+    fn set_mem_halfword(&mut self, loc : usize, val : u16) -> Result<(), RuntimeError> {
+        self.check_writable(loc, 2)?;
+        self.log_write(loc, val as u32);
+        let bytes = match self.endian {
+            Endian::Little => val.to_le_bytes(),
+            Endian::Big => val.to_be_bytes()
+        };
+        self.memory[loc..loc+2].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /* Get the word at a given memory location in the data byte order (set_endian), independent of host endianness
+    loc     <-  location of the start of the 4 bytes in memory (word aligned)
+    return  <-  the word, OutOfBounds past the end of memory, or UnalignedAccess */
+    fn get_mem_word(&self, loc : usize) -> Result<u32, RuntimeError> {self.read_word(loc, self.endian)}
+
+    /* Get the word at a given memory location in a given byte order, e.g. little endian for instruction fetches
+    and memory dumps */
+    fn read_word(&self, loc : usize, endian : Endian) -> Result<u32, RuntimeError> {
+        match self.memory.get(loc..loc.saturating_add(4)) {
+            Some(&[b0, b1, b2, b3]) if loc.is_multiple_of(4) => Ok(match endian {
+                Endian::Little => u32::from_le_bytes([b0, b1, b2, b3]),
+                Endian::Big => u32::from_be_bytes([b0, b1, b2, b3])
+            }),
+            Some(_) => Err(RuntimeError::UnalignedAccess {addr : loc}),
+            None => Err(RuntimeError::OutOfBounds {addr : loc})
+        }
+    }
+
+    /* Set the word at a given memory location, stored in the data byte order independent of host endianness
+    loc     <-  location of the start of the 4 bytes in memory (word aligned)
+    val     <-  the value to be written
+    return  <-  OutOfBounds if the word extends past the end of memory, UnalignedAccess if loc is not word aligned,
+                WriteToReadOnly if the word is in the ROM range */
+    fn set_mem_word(&mut self, loc : usize, val : u32) -> Result<(), RuntimeError> {
+        if loc.saturating_add(4) > self.memory.len() {return Err(RuntimeError::OutOfBounds {addr : loc})}
+        if !loc.is_multiple_of(4) {return Err(RuntimeError::UnalignedAccess {addr : loc})}
+        self.check_writable(loc, 4)?;
+        self.log_write(loc, val);
+        let bytes = match self.endian {
+            Endian::Little => val.to_le_bytes(),
+            Endian::Big => val.to_be_bytes()
+        };
+        if let Some(word) = self.memory.get_mut(loc..loc + 4) {word.copy_from_slice(&bytes)}
+        Ok(())
+    }
+
+    /* Check that a store does not touch the ROM range
+    loc     <-  location of the first byte stored
+    size    <-  number of bytes stored */
+    fn check_writable(&self, loc : usize, size : usize) -> Result<(), RuntimeError> {
+        match &self.rom {
+            Some(rom) if loc < rom.end && rom.start < loc.saturating_add(size) => Err(RuntimeError::WriteToReadOnly {addr : loc.max(rom.start)}),
+            _ => Ok(())
+        }
+    }
+
+    /* Add a store to the write log when recording is enabled
+    loc     <-  location of the first byte stored
+    val     <-  the value stored */
+    fn log_write(&mut self, loc : usize, val : u32) {
+        if self.record_writes {self.write_log.push((self.instructions, loc, val))}
+    }
+
+    // EMULATION MAIN FUNCTIONS-------------------------------------------------
+    /* Get the file at 'filename' and load its contents into memory 
+    filename <- relative path from executable to file
+    return   <- LoadError if the file cannot be read or does not fit in memory */
+    pub fn load_program(&mut self, filename : &str) -> Result<(), LoadError> {
+        self.load_program_from_reader(&mut File::open(filename)?)
+    }
+
+    /* Read a program from any byte source (e.g. stdin) and load it into memory. A program may fill memory
+    entirely, as the pipeline only faults on an out of bounds prefetch if that instruction is executed.
+    ELF executables have their PT_LOAD segments copied to their addresses and set the entry point used by
+    run_program, files starting with ':' are loaded as Intel HEX (see load_ihex), anything else is loaded
+    as a flat binary at address 0 with entry point 0.
+    reader   <- the source, read until end of file
+    return   <- LoadError if the source cannot be read or does not fit in memory */
+    pub fn load_program_from_reader<R : Read>(&mut self, reader : &mut R) -> Result<(), LoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        if elf::is_elf(&bytes) {
+            let (entry, segments) = elf::parse(&bytes)?;
+            /* check every segment before loading any so a failed load leaves memory untouched */
+            if let Some(segment) = segments.iter().find(|segment| segment.addr.saturating_add(segment.memsize) > self.memory.len()) {
+                return Err(LoadError::SegmentOutOfRange {addr : segment.addr, size : segment.memsize});
+            }
+            for segment in segments {
+                self.load_segment(segment.data, segment.addr)?;
+                self.memory[segment.addr + segment.data.len()..segment.addr + segment.memsize].fill(0);
+            }
+            self.entry = entry;
+            Ok(())
+        } else if ihex::is_ihex(&bytes) {
+            self.load_ihex(&String::from_utf8_lossy(&bytes))
+        } else if bytes.len() <= self.memory.len() {
+            self.load_segment(&bytes, 0)?;
+            self.entry = 0;
+            Ok(())
+        } else {
+            Err(LoadError::TooLarge {size : bytes.len(), capacity : self.memory.len()})
+        }
+    }
+
+    /* Copy bytes into memory at an offset, e.g. to load separate code and data images. Memory outside the
+    segment and the entry point are unchanged.
+    bytes    <- the segment contents
+    at       <- address of the first byte
+    return   <- SegmentOutOfRange if the segment does not fit in memory (nothing is copied) */
+    pub fn load_segment(&mut self, bytes : &[u8], at : usize) -> Result<(), LoadError> {
+        let segment = self.memory.get_mut(at..at.saturating_add(bytes.len())).ok_or(LoadError::SegmentOutOfRange {addr : at, size : bytes.len()})?;
+        segment.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /* Load a program in Intel HEX format, writing each data record to its address. Extended segment and
+    linear address records set the base of the following records, parsing stops at the end of file record
+    and a start address record sets the entry point used by run_program (0 otherwise).
+    text     <- the records, one per line
+    return   <- InvalidHex if a record is malformed, SegmentOutOfRange if data lies outside memory */
+    pub fn load_ihex(&mut self, text : &str) -> Result<(), LoadError> {
+        let (start, records) = ihex::parse(text)?;
+        /* check every record before loading any so a failed load leaves memory untouched */
+        if let Some(record) = records.iter().find(|record| record.addr + record.data.len() > self.memory.len()) {
+            return Err(LoadError::SegmentOutOfRange {addr : record.addr, size : record.data.len()});
+        }
+        for record in records {self.load_segment(&record.data, record.addr)?}
+        self.entry = start.unwrap_or(0);
+        Ok(())
+    }
+
+    /* Run the main loop, fetching, decoding and executing instructions from the entry point (address 0 unless
+    set by an ELF executable) until the program halts
+    return  <-  Halted, Breakpoint(addr) if execution stopped at a breakpoint, or the error that stopped execution */
+    pub fn run_program(&mut self) -> Result<StepResult, RuntimeError> {self.run_from(self.entry)}
+
+    /* Run the main loop starting with the instruction at entry (the pipeline is refilled from there)
+    entry   <-  address of the first instruction (word aligned)
+    return  <-  as for run_program */
+    pub fn run_from(&mut self, entry : u32) -> Result<StepResult, RuntimeError> {
+        self.write_register(PC, entry);
+        self.halted = false;
+        if self.breakpoints.contains(&entry) {return Ok(StepResult::Breakpoint(entry))}
+        self.resume()
+    }
+
+    /* Continue execution from the current state until the program halts or reaches a breakpoint. The
+    instruction at the current PC is always executed, so resuming from a breakpoint makes progress.
+    return  <-  Halted, Breakpoint(addr) stopped before executing addr, Watchpoint after the instruction
+                accessing a watched address, or the error that stopped execution */
+    pub fn resume(&mut self) -> Result<StepResult, RuntimeError> {
+        let mut first = true;
+        loop {
+            let next = self.next_address();
+            if !first && self.breakpoints.contains(&next) {return Ok(StepResult::Breakpoint(next))}
+            first = false;
+            if let Some(limit) = self.max_instructions.filter(|limit| self.instructions >= *limit) {
+                return Err(RuntimeError::InstructionLimitExceeded {limit});
+            }
+
+            if let watch @ StepResult::Watchpoint {..} = self.step()? {return Ok(watch)}
+            if self.halted {return Ok(StepResult::Halted)}
+        }
+    }
+
+    /* Run the loaded program as run_program does, timing it without printing anything
+    return  <-  the wall clock time taken, the number of instructions executed and their rate, or the error that stopped execution */
+    pub fn run_benchmark(&mut self) -> Result<BenchStats, RuntimeError> {
+        let (start, before) = (Instant::now(), self.instructions);
+        self.run_program()?;
+        let elapsed = start.elapsed();
+        let instructions = self.instructions - before;
+        Ok(BenchStats {elapsed, instructions, instructions_per_second : instructions as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE)})
+    }
+
+    /* Stop execution before the instruction at addr is executed
+    addr    <-  address of the instruction */
+    pub fn add_breakpoint(&mut self, addr : u32) {self.breakpoints.insert(addr);}
+
+    /* Remove a breakpoint, returning true if one was set at addr
+    addr    <-  address of the instruction */
+    pub fn remove_breakpoint(&mut self, addr : u32) -> bool {self.breakpoints.remove(&addr)}
+
+    /* Stop execution after an instruction that accesses the byte at addr (with Read, Write or either)
+    addr    <-  memory address to watch
+    kind    <-  the accesses to stop on */
+    pub fn add_watchpoint(&mut self, addr : usize, kind : WatchKind) {self.watchpoints.insert(addr, kind);}
+
+    /* Remove a watchpoint, returning true if one was set at addr
+    addr    <-  memory address watched */
+    pub fn remove_watchpoint(&mut self, addr : usize) -> bool {self.watchpoints.remove(&addr).is_some()}
+
+    /* Record the first watched byte touched by a data transfer, to be reported once the instruction completes
+    loc     <-  address of the access
+    size    <-  number of bytes accessed
+    write   <-  true for a store, false for a load */
+    fn check_watchpoints(&mut self, loc : usize, size : usize, write : bool) {
+        if self.watchpoints.is_empty() || self.watch_hit.is_some() {return}
+        let kind = if write {WatchKind::Write} else {WatchKind::Read};
+        self.watch_hit = (loc..loc.saturating_add(size))
+            .find(|addr| matches!(self.watchpoints.get(addr), Some(watch) if *watch == kind || *watch == WatchKind::Access))
+            .map(|addr| (addr, kind));
+    }
+
+    /* Get the address of the next instruction to be executed (the oldest instruction in the pipeline) */
+    fn next_address(&self) -> u32 {
+        self.pipeline.decoded.as_ref().or(self.pipeline.fetched.as_ref()).map_or(self.registers[PC], |(addr, _)| *addr)
+    }
+
+    /* Run the pipeline until a single instruction has been executed, refilling it first after a branch.
+    return  <-  Halted on a halt SWI (or zero word if halt_on_zero is set), Branched if the PC was redirected, otherwise Continued
+                RuntimeError if the instruction cannot be fetched or executed */
+    pub fn step(&mut self) -> Result<StepResult, RuntimeError> {
+        loop {
+            if self.halted {return Ok(StepResult::Halted)}
+            if let Some(result) = self.pipeline_cycle()? {return Ok(result)}
+        }
+    }
+
+    /* Advance the pipeline by one stage: execute the decoded instruction, then move the fetched instruction to
+    decode and fetch the next from the PC. The PC runs two instructions ahead of the execute stage, so during
+    execution it reads as the instruction address + 8. The pipeline stops advancing once the machine halts.
+    return  <-  the result of the executed instruction, or None while the pipeline is filling */
+    fn pipeline_cycle(&mut self) -> Result<Option<StepResult>, RuntimeError> {
+        let executed = match self.pipeline.decoded.take() {
+            Some((addr, instruction)) => Some(self.execute(addr, instruction?)?),
+            None => None
+        };
+        if self.halted {return Ok(executed)}
+
+        let pc = self.registers[PC];
+        self.pipeline.decoded = self.pipeline.fetched.take();
+        self.pipeline.fetched = Some((pc, self.fetch(pc)));
+        self.registers[PC] = pc.wrapping_add(if self.thumb {THUMB_INSTRUCTION_SIZE} else {4});
+        Ok(executed)
+    }
+
+    /* Fetch the instruction word (or Thumb halfword) at an address
+    addr    <-  address of the instruction */
+    fn fetch(&self, addr : u32) -> Result<u32, RuntimeError> {
+        let loc = addr as usize;
+        if !self.thumb {return self.read_word(loc, Endian::Little)}
+        if loc.saturating_add(2) > self.memory.len() {return Err(RuntimeError::OutOfBounds {addr : loc})}
+        if !loc.is_multiple_of(2) {return Err(RuntimeError::UnalignedAccess {addr : loc})}
+        Ok(u16::from_le_bytes(self.memory[loc..loc+2].try_into().unwrap()) as u32)
+    }
+
+    /* Execute an instruction in the execute stage, instructions writing the PC flush the pipeline with write_register
+    addr                <-  address the instruction was fetched from
+    current_instruction <-  the instruction word */
+    fn execute(&mut self, addr : u32, current_instruction : u32) -> Result<StepResult, RuntimeError> {
+        /* the end of a program is its code length if set, otherwise uninitialised memory (zero or the poison pattern) */
+        let uninitialised = current_instruction == 0 || self.poison == Some(current_instruction);
+        if self.code_end.is_some_and(|end| addr >= end) || (self.halt_on_zero && uninitialised) {
+            self.halted = true;
+            return Ok(StepResult::Halted);
+        }
+
+        self.instructions += 1;
+        if let Some(executed) = self.executed.get_mut(addr as usize / 2) {*executed = true}
+        let before = self.registers;
+        let result = if self.thumb {
+            self.thumb_instruction(addr, current_instruction as u16)
+        } else {
+            if self.trace {writeln!(self.trace_output, "{:#010x}: {:#010x}  {}", addr, current_instruction, disasm::disassemble_at(&current_instruction, addr)).ok();}
+            self.arm_instruction(&current_instruction)
+        };
+        /* a watched access that faulted is not reported by a later instruction */
+        if let Err(err) = result {
+            self.watch_hit = None;
+            return Err(err);
+        }
+
+        if self.trace {self.trace_registers(&before)}
+        if self.halted {return Ok(StepResult::Halted)}
+        if let Some((addr, kind)) = self.watch_hit.take() {return Ok(StepResult::Watchpoint {addr, kind})}
+        /* the next instruction is always fetched by now, unless writing the PC flushed the pipeline */
+        Ok(if self.pipeline.fetched.is_none() {StepResult::Branched} else {StepResult::Continued})
+    }
+
+    /* Execute an ARM instruction if its condition passes
+    current_instruction <-  the instruction word */
+    fn arm_instruction(&mut self, current_instruction : &u32) -> Result<(), RuntimeError> {
+        let current_instruction = *current_instruction;
+        if self.check_condition(&current_instruction) {
+            match instruction_type(&current_instruction) {
+                InstructionType::SoftwareInterrupt => self.software_interrupt_instruction(&current_instruction)?,
+                InstructionType::Branch => self.branch_instruction(&current_instruction)?,
+                InstructionType::BlockDataTransfer => self.block_data_transfer_instruction(&current_instruction)?,
+                InstructionType::SingleDataTransfer => self.single_data_transfer_instruction(&current_instruction)?,
+                InstructionType::Multiply => self.multiple_instruction(&current_instruction)?,
+                InstructionType::LongMultiply => self.long_multiply_instruction(&current_instruction)?,
+                InstructionType::HalfwordDataTransfer => self.halfword_data_transfer_instruction(&current_instruction)?,
+                InstructionType::BranchExchange => self.branch_exchange_instruction(&current_instruction)?,
+                InstructionType::SaturatingArithmetic => self.saturating_instruction(&current_instruction)?,
+                InstructionType::CountLeadingZeros => self.clz_instruction(&current_instruction)?,
+                InstructionType::MoveFromStatus => self.mrs_instruction(&current_instruction)?,
+                InstructionType::MoveToStatus => self.msr_instruction(&current_instruction)?,
+                InstructionType::DataProcessing => self.process_data_instruction(&current_instruction)?,
+                InstructionType::Coprocessor => self.coprocessor_instruction(&current_instruction)?,
+                InstructionType::Unknown => return Err(RuntimeError::UnknownInstruction {instruction : current_instruction})
+            }
+        } else {
+            self.cycles += SKIPPED_CYCLES;
+        }
+        Ok(())
+    }
+
+    /* Execute a Thumb instruction, running its ARM translation through the ARM handlers. Branches and PC relative
+    operations are executed directly as the Thumb PC (instruction address + 4) is only halfword aligned.
+    addr        <-  address of the instruction
+    instruction <-  the Thumb halfword */
+    fn thumb_instruction(&mut self, addr : u32, instruction : u16) -> Result<(), RuntimeError> {
+        let op = thumb::translate(instruction).ok_or(RuntimeError::InvalidInstruction {instruction : instruction as u32, reason : "Unsupported Thumb instruction"})?;
+        if self.trace {writeln!(self.trace_output, "{:#010x}: {:#06x}      {}", addr, instruction, thumb::describe(&op, addr)).ok();}
+
+        let pc = self.registers[PC];
+        match op {
+            ThumbOp::Arm(word) => return self.arm_instruction(&word),
+            ThumbOp::Branch {cond, offset} => {
+                if !self.check_condition(&(cond << 28)) {
+                    self.cycles += SKIPPED_CYCLES;
+                    return Ok(());
+                }
+                self.thumb_branch(pc.wrapping_add(offset as u32))?;
+            },
+            ThumbOp::LinkHigh {offset} => {
+                self.registers[LR] = pc.wrapping_add(offset as u32);
+                self.cycles += DATA_PROCESSING_CYCLES;
+            },
+            ThumbOp::LinkLow {offset} => {
+                let target = self.registers[LR].wrapping_add(offset);
+                self.thumb_branch(target)?;
+                /* return to the instruction after the bl, with bit 0 marking the Thumb state */
+                self.registers[LR] = pc.wrapping_sub(THUMB_INSTRUCTION_SIZE) | 1;
+            },
+            ThumbOp::LoadLiteral {rd, offset} => {
+                let memloc = ((pc & !3) + offset) as usize;
+                self.check_watchpoints(memloc, 4, false);
+                self.registers[rd] = self.get_mem_word(memloc)?;
+                self.cycles += LOAD_CYCLES;
+            },
+            ThumbOp::AddPc {rd, offset} => {
+                self.registers[rd] = (pc & !3) + offset;
+                self.cycles += DATA_PROCESSING_CYCLES;
+            },
+            ThumbOp::Multiply {rd, rs} => {
+                let rs_val = self.registers[rs];
+                let result = self.registers[rd].wrapping_mul(rs_val);
+                self.registers[rd] = result;
+                self.set_multiply_flags(result as u64, 32);
+                self.cycles += MULTIPLY_CYCLES + multiply_cycles(rs_val);
+            }
+        }
+        Ok(())
+    }
+
+    /* Branch to a Thumb instruction, checking it is halfword aligned and inside memory
+    target  <-  address of the instruction to branch to */
+    fn thumb_branch(&mut self, target : u32) -> Result<(), RuntimeError> {
+        if !target.is_multiple_of(2) || (target as usize).saturating_add(2) > self.memory.len() {return Err(RuntimeError::BranchOutOfRange {target})}
+        self.write_register(PC, target);
+        self.cycles += BRANCH_CYCLES;
+        Ok(())
+    }
+
+    /* Trace each register (other than the PC) changed by the last instruction
+    before  <-  register values before the instruction executed */
+    fn trace_registers(&mut self, before : &[u32; 16]) {
+        for (reg, (old, new)) in before.iter().zip(self.registers.iter()).enumerate().take(PC) {
+            if old != new {writeln!(self.trace_output, "            r{} = {:#010x}", reg, new).ok();}
+        }
+    }
+
+    // print the register and non-zero memory to the terminal
+    pub fn print_state(&self) {self.write_state(&mut io::stdout().lock()).expect("failed printing to stdout")}
+
+    /* Write the registers and non-zero memory in the print_state format
+    out     <-  where to write the state, e.g. a buffer to compare against expected output */
+    pub fn write_state<W : Write>(&self, out : &mut W) -> io::Result<()> {
+        writeln!(out, "Registers:")?;
+        for (ind, regval) in self.registers[..13].iter().enumerate() {
+            writeln!(out, "${reg:<3}: {val:>10} ({val:#010x})", reg=ind, val=*regval as i32)?;
+        }
+        for reg in [SP, LR, PC] {
+            writeln!(out, "{name:<4}: {val:>10} ({val:#010x})", name=reg_name(reg as u32).to_uppercase(), val=self.registers[reg] as i32)?;
+        }
+        writeln!(out, "CPSR: {val:>10} ({val:#010x})", val=self.get_cpsr() as i32)?;
+        writeln!(out, "Cycles: {}", self.cycles)?;
+        writeln!(out, "Non-zero memory:")?;
+        for (loc, val) in self.dump_nonzero() {
+            /* display bytes in memory order */
+            writeln!(out, "{loc:#010x}: {val:#010x}", loc=loc, val=val.swap_bytes())?;
+        }
+        Ok(())
+    }
+
+    /* Print the registers, CPSR and non-zero memory as comma separated values for machine parsing:
+    one 'name,decimal,hex' line per register (r0-r12, sp, lr, pc, cpsr), then one 'address,hex' line per word,
+    with the word shown in memory byte order as print_state does */
+    pub fn print_state_csv(&self) {self.write_state_csv(&mut io::stdout().lock()).expect("failed printing to stdout")}
+
+    /* Write the registers, CPSR and non-zero memory in the print_state_csv format
+    out     <-  where to write the values */
+    pub fn write_state_csv<W : Write>(&self, out : &mut W) -> io::Result<()> {
+        for (ind, regval) in self.registers.iter().enumerate() {
+            writeln!(out, "{},{},{:#010x}", reg_name(ind as u32), *regval as i32, regval)?;
+        }
+        writeln!(out, "cpsr,{},{:#010x}", self.get_cpsr() as i32, self.get_cpsr())?;
+        for (loc, val) in self.dump_nonzero() {
+            writeln!(out, "{:#010x},{:#010x}", loc, val.swap_bytes())?;
+        }
+        Ok(())
+    }
+
+    /* Collect the non-zero words of memory
+    return  <-  (address, word) pairs in address order, words read little endian */
+    pub fn dump_nonzero(&self) -> Vec<(usize, u32)> {
+        (0..self.memory.len().saturating_sub(3)).step_by(4)
+            .filter_map(|loc| self.read_word(loc, Endian::Little).ok().filter(|val| *val != 0).map(|val| (loc, val)))
+            .collect()
+    }
+
+    /* Write the entire memory image to a file as raw bytes (in memory order), compatible with load_program
+    path    <-  the file to create or overwrite */
+    pub fn dump_memory(&self, path : &str) -> io::Result<()> {write(path, &self.memory)}
+
+    /* Serialize the machine state as a JSON object, e.g.
+    {"registers":[0,...],"cpsr":{"n":false,"z":false,"c":false,"v":false},"cycles":0,"memory":[{"address":0,"value":0}]}
+    Registers and memory words are unsigned numbers, memory lists only the non-zero words. */
+    pub fn state_json(&self) -> String {
+        let registers : Vec<String> = self.registers.iter().map(u32::to_string).collect();
+        let memory : Vec<String> = self.dump_nonzero().iter()
+            .map(|(loc, val)| format!("{{\"address\":{},\"value\":{}}}", loc, val))
+            .collect();
+        format!(
+            "{{\"registers\":[{}],\"cpsr\":{{\"n\":{},\"z\":{},\"c\":{},\"v\":{}}},\"cycles\":{},\"memory\":[{}]}}",
+            registers.join(","), self.cpsr.n, self.cpsr.z, self.cpsr.c, self.cpsr.v, self.cycles, memory.join(",")
+        )
+    }
+
+    /* INSTRUCTION PROCESSING-------------------------------------------------*/
+    /* execute a branch instruction, updating the PC (and the LR if the L bit is set)
+    The 24 bit offset is sign extended and shifted to a byte offset, then added to the PC (instruction address + 8).
+    Targets outside memory or not word aligned raise BranchOutOfRange without changing any registers. */
+    fn branch_instruction(&mut self, instruction: &u32) -> Result<(), RuntimeError> {
+        let offset = ((get_bits(instruction, 0, 24) << 8) as i32) >> 6;
+        /* a target before address 0 wraps around to a large address, so is caught by the bounds check */
+        let target = self.registers[PC].wrapping_add(offset as u32);
+        if !target.is_multiple_of(4) || (target as usize).saturating_add(4) > self.memory.len() {return Err(RuntimeError::BranchOutOfRange {target})}
+
+        if get_bit(instruction, 24) {self.registers[LR] = self.registers[PC].wrapping_sub(4)}
+        self.write_register(PC, target);
+        self.cycles += BRANCH_CYCLES;
+        Ok(())
+    }
+
+    /* execute a BX instruction, branching to the address in Rm and entering the Thumb state if bit 0 of it is set
+    (bit 0 is cleared from the target). Targets outside memory or misaligned for the new state raise BranchOutOfRange. */
+    fn branch_exchange_instruction(&mut self, instruction : &u32) -> Result<(), RuntimeError> {
+        let value = self.registers[get_bits(instruction, 0, 4) as usize];
+        let (thumb, target) = (get_bit(&value, 0), value & !1);
+        let size = if thumb {THUMB_INSTRUCTION_SIZE} else {4};
+        if !target.is_multiple_of(size) || (target as usize).saturating_add(size as usize) > self.memory.len() {return Err(RuntimeError::BranchOutOfRange {target})}
+
+        self.thumb = thumb;
+        self.write_register(PC, target);
+        self.cycles += BRANCH_CYCLES;
+        Ok(())
+    }
+
+    /* execute a coprocessor instruction, no coprocessors are attached so this fails with UnsupportedCoprocessor
+    unless they are being skipped (see set_skip_coprocessor) */
+    fn coprocessor_instruction(&mut self, instruction : &u32) -> Result<(), RuntimeError> {
+        let cp_num = get_bits(instruction, 8, 4);
+        if !self.skip_coprocessor {return Err(RuntimeError::UnsupportedCoprocessor {cp_num})}
+        if self.trace {writeln!(self.trace_output, "            skipped coprocessor p{} instruction", cp_num).ok();}
+        self.cycles += SKIPPED_CYCLES;
+        Ok(())
+    }
+
+    /* execute a software interrupt, dispatching on the 24 bit comment field:
+    SWI 0 writes the low byte of R0 to the output (see set_output) as a character, SWI 1 halts the machine */
+    fn software_interrupt_instruction(&mut self, instruction : &u32) -> Result<(), RuntimeError> {
+        match get_bits(instruction, 0, 24) {
+            SWI_WRITE_CHAR => {
+                write!(self.output, "{}", self.registers[0] as u8 as char).and_then(|_| self.output.flush()).ok();
+            },
+            SWI_HALT => self.halted = true,
+            _ => return Err(RuntimeError::invalid(instruction, "Unknown software interrupt"))
+        }
+        self.cycles += SOFTWARE_INTERRUPT_CYCLES;
+        Ok(())
+    }
+
+    /* use condition bits of an instruction and the current cpsr to determine if an instruction should be executed
+    instruction <-  the instruction, condition code held in bits 28-31 */
+    fn check_condition(&self, instruction: &u32) -> bool {
+        match get_bits(instruction, 28, 4) {
+            EQ => self.cpsr.z,
+            NE => !self.cpsr.z,
+            CS => self.cpsr.c,
+            CC => !self.cpsr.c,
+            MI => self.cpsr.n,
+            PL => !self.cpsr.n,
+            VS => self.cpsr.v,
+            VC => !self.cpsr.v,
+            HI => self.cpsr.c && !self.cpsr.z,
+            LS => !self.cpsr.c || self.cpsr.z,
+            GE => self.cpsr.n == self.cpsr.v,
+            LT => self.cpsr.n != self.cpsr.v,
+            GT => !self.cpsr.z && (self.cpsr.n == self.cpsr.v),
+            LE => self.cpsr.z || (self.cpsr.n != self.cpsr.v),
+            AL => true,
+            _ => false
+        }
+    }
+
+    fn shift_operation(&mut self, instruction : &u32) -> Result<(u32, bool), RuntimeError> {
+        let rm = get_bits(instruction, 0, 4) as usize;
+        /* an Rm of PC reads as the instruction address + 8, except with a register shift where it is unpredictable */
+        if get_bit(instruction, 4) && (rm == PC || get_bits(instruction, 8, 4) as usize == PC) {
+            return Err(RuntimeError::invalid(instruction, "Register shift uses PC as Rm or Rs"));
+        }
+
+        let rm_value = self.registers[rm];
+        let shift_amount = 
+            if !get_bit(instruction, 4) {
+                /* <int>__0 case -> shift by immediate value, where lsr #0 and asr #0 encode a shift by 32
+                and ror #0 encodes rrx (a 33 bit rotate right by one through the carry flag) */
+                match (get_bits(instruction, 7, 5), get_bits(instruction, 5, 2)) {
+                    (0, 0b01) | (0, 0b10) => 32,
+                    (0, 0b11) => return Ok(((self.cpsr.c as u32) << 31 | rm_value >> 1, get_bit(&rm_value, 0))),
+                    (amount, _) => amount
+                }
+            } else if !get_bit(instruction, 7) {
+                /* <RS>0__1 case -> shift specified by the bottom byte of a register, a zero amount leaves the carry unchanged */
+                let amount = self.registers[get_bits(instruction, 8, 4) as usize] & 0xFF;
+                if amount == 0 {return Ok((rm_value, self.cpsr.c))}
+                amount
+            } else {
+                return Err(RuntimeError::invalid(instruction, "Shift neither by constant, nor by register"));
+            };
+        
+        /* determine shift type and overflow/carryout using bits 5 & 6 of the instruction (no shift leaves the carry unchanged),
+        shifts of 32 or more move every bit out (lsl/lsr give 0, asr gives the sign bit) */
+        Ok(if shift_amount == 0 {
+            (rm_value, self.cpsr.c)
+        } else {
+            match (get_bit(instruction, 6), get_bit(instruction, 5)) {
+                /* logical left shift (lsl) */ (false, false) => (
+                    rm_value.checked_shl(shift_amount).unwrap_or(0),
+                    shift_amount <= 32 && get_bit(&rm_value, 32 - shift_amount)
+                ),
+                /* logical right shift (lsr) */ (false, true) => (
+                    rm_value.checked_shr(shift_amount).unwrap_or(0),
+                    shift_amount <= 32 && get_bit(&rm_value, shift_amount - 1)
+                ),
+                /* arithmetic right shift (asr) */ (true, false) => (
+                    (rm_value as i32).wrapping_shr(shift_amount.min(31)) as u32,
+                    get_bit(&rm_value, shift_amount.min(32) - 1)
+                ),
+                /* rotate right shift (ror), by the amount mod 32 with a multiple of 32 giving rm and carry out bit 31 */ _ => (
+                    rm_value.rotate_right(shift_amount % 32),
+                    get_bit(&rm_value, (shift_amount + 31) % 32)
+                )
+            }
+        })
+    }
+
+    fn single_data_transfer_instruction(&mut self, instruction: &u32) -> Result<(), RuntimeError> {
+        let rn_reg = get_bits(instruction, 16, 4) as usize;
+        let rd_reg = get_bits(instruction, 12, 4) as usize;
+
+        let i = get_bit(instruction, 25);
+        let p = get_bit(instruction, 24);
+        let u = get_bit(instruction, 23);
+        let b = get_bit(instruction, 22);
+        let w = get_bit(instruction, 21);
+        let l = get_bit(instruction, 20);
+    
+        if PC == rd_reg {return Err(RuntimeError::invalid(instruction, "Data Transfer instruction uses PC as Rd"))}
+        if PC == rn_reg && (!p || w) {return Err(RuntimeError::unpredictable(instruction, "Data Transfer instruction writes back to PC"))}
+        if rn_reg == rd_reg && (!p || w) {return Err(RuntimeError::unpredictable(instruction, "Data Transfer instruction writes back to Rd"))}
+
+        let offset = if i {
+            if get_bits(instruction, 0, 4) as usize == rd_reg && !p {return Err(RuntimeError::invalid(instruction, "Data Transfer instruction uses same register as Rn, Rm"))}
+            if get_bits(instruction, 0, 4) as usize == PC {return Err(RuntimeError::invalid(instruction, "Data Transfer instruction uses PC as Rm"))}
+            self.shift_operation(instruction)?.0
+        } else {get_bits(instruction, 0, 12)};
+
+        let rd_val = self.registers[rd_reg];
+        let (memloc, indexed) = self.transfer_address(rn_reg, offset, p, u);
+        self.check_watchpoints(memloc, if b {1} else {4}, !l);
+        if let Some(name) = special_address_name(memloc as u32).filter(|_| self.trace) {
+            writeln!(self.trace_output, "            {} {} ({:#010x})", if l {"load from"} else {"store to"}, name, memloc).ok();
+        }
+
+        if let Some(device) = self.device_at(memloc) {
+            if l {self.registers[rd_reg] = device.read(memloc as u32)}
+            else {device.write(memloc as u32, rd_val)}
+        } else if memloc.saturating_add(if b {1} else {4}) > self.memory.len() {
+            return Err(RuntimeError::OutOfBounds {addr : memloc});
+        } else {
+            match (l, b) {
+                (true, false) => self.registers[rd_reg] = self.get_mem_word(memloc)?,
+                (true, true) => self.registers[rd_reg] = self.get_mem_byte(memloc) as u32,
+                (false, false) => self.set_mem_word(memloc, rd_val)?,
+                (false, true) => self.set_mem_byte(memloc, rd_val as u8)?
+            }
+        }
+        if !p || w {self.registers[rn_reg] = indexed}
+        self.cycles += if l {LOAD_CYCLES} else {STORE_CYCLES};
+        Ok(())
+    }
+
+    /* execute a halfword or signed byte transfer (LDRH/STRH/LDRSB/LDRSH), with either a split 8 bit
+    immediate offset (bit 22 set) or a register offset. Bits 6 (signed) and 5 (halfword) give the type. */
+    fn halfword_data_transfer_instruction(&mut self, instruction : &u32) -> Result<(), RuntimeError> {
+        let rn_reg = get_bits(instruction, 16, 4) as usize;
+        let rd_reg = get_bits(instruction, 12, 4) as usize;
+
+        let p = get_bit(instruction, 24);
+        let u = get_bit(instruction, 23);
+        let i = get_bit(instruction, 22);
+        let w = get_bit(instruction, 21);
+        let l = get_bit(instruction, 20);
+        let s = get_bit(instruction, 6);
+        let h = get_bit(instruction, 5);
+
+        if PC == rd_reg {return Err(RuntimeError::invalid(instruction, "Halfword Data Transfer instruction uses PC as Rd"))}
+        if PC == rn_reg && (!p || w) {return Err(RuntimeError::unpredictable(instruction, "Halfword Data Transfer instruction writes back to PC"))}
+        if rn_reg == rd_reg && (!p || w) {return Err(RuntimeError::unpredictable(instruction, "Halfword Data Transfer instruction writes back to Rd"))}
+        if !i && get_bits(instruction, 0, 4) as usize == PC {return Err(RuntimeError::invalid(instruction, "Halfword Data Transfer instruction uses PC as Rm"))}
+        if !l && s {return Err(RuntimeError::invalid(instruction, "Halfword Data Transfer instruction stores a signed value"))}
+
+        let offset = if i {
+            get_bits(instruction, 8, 4) << 4 | get_bits(instruction, 0, 4)
+        } else {self.registers[get_bits(instruction, 0, 4) as usize]};
+
+        let rd_val = self.registers[rd_reg];
+        let (memloc, indexed) = self.transfer_address(rn_reg, offset, p, u);
+        self.check_watchpoints(memloc, if h {2} else {1}, !l);
+
+        if memloc.saturating_add(if h {2} else {1}) > self.memory.len() {
+            return Err(RuntimeError::OutOfBounds {addr : memloc});
+        } else if l {
+            self.registers[rd_reg] = match (s, h) {
+                /* signed values are sign extended to 32 bits */
+                (true, false) => self.get_mem_byte(memloc) as i8 as u32,
+                (true, true) => self.get_mem_halfword(memloc) as i16 as u32,
+                _ => self.get_mem_halfword(memloc) as u32
+            };
+        } else {
+            self.set_mem_halfword(memloc, rd_val as u16)?;
+        }
+        if !p || w {self.registers[rn_reg] = indexed}
+        self.cycles += if l {LOAD_CYCLES} else {STORE_CYCLES};
+        Ok(())
+    }
+
+    /* Get the address of a data transfer and the indexed base, which the caller writes back to the base register
+    (when post-indexed or pre-indexed with the W bit set) once the access has succeeded. A PC base reads as the
+    instruction address + 8 rounded down to a word, for PC relative literal loads (writeback to the PC is rejected
+    by the callers).
+    rn_reg  <-  base register
+    offset  <-  unsigned offset magnitude (immediate or shifted register)
+    p       <-  pre-indexed (offset applied before the transfer)
+    u       <-  add the offset to the base, otherwise subtract it
+    return  <-  (address accessed, base plus or minus the offset) */
+    fn transfer_address(&self, rn_reg : usize, offset : u32, p : bool, u : bool) -> (usize, u32) {
+        let base = if rn_reg == PC {self.registers[PC] & !3} else {self.registers[rn_reg]};
+        let indexed = if u {base.wrapping_add(offset)} else {base.wrapping_sub(offset)};
+        ((if p {indexed} else {base}) as usize, indexed)
+    }
+
+    /* execute a block data transfer (LDM/STM), moving each register in the 16 bit register list to/from
+    consecutive words. The lowest numbered register always uses the lowest address, P/U select the
+    addressing mode (IA/IB/DA/DB) and W writes the final address back to Rn. */
+    fn block_data_transfer_instruction(&mut self, instruction : &u32) -> Result<(), RuntimeError> {
+        let rn_reg = get_bits(instruction, 16, 4) as usize;
+        let reg_list = get_bits(instruction, 0, 16);
+
+        let p = get_bit(instruction, 24);
+        let u = get_bit(instruction, 23);
+        let w = get_bit(instruction, 21);
+        let l = get_bit(instruction, 20);
+
+        if rn_reg == PC {return Err(RuntimeError::invalid(instruction, "Block Data Transfer instruction uses PC as Rn"))}
+
+        let base = self.registers[rn_reg];
+        let size = reg_list.count_ones() * 4;
+        let mut memloc = match (p, u) {
+            /* increment after */  (false, true) => base,
+            /* increment before */ (true, true) => base.wrapping_add(4),
+            /* decrement after */  (false, false) => base.wrapping_sub(size).wrapping_add(4),
+            /* decrement before */ (true, false) => base.wrapping_sub(size)
+        } as usize;
+
+        for reg in (0..16).filter(|reg| get_bit(&reg_list, *reg as u32)) {
+            self.check_watchpoints(memloc, 4, !l);
+            if memloc + 4 > self.memory.len() {
+                return Err(RuntimeError::OutOfBounds {addr : memloc});
+            } else if l {
+                let val = self.get_mem_word(memloc)?;
+                self.write_register(reg, val);
+            } else {
+                self.set_mem_word(memloc, self.registers[reg])?;
+            }
+            memloc += 4;
+        }
+
+        /* a loaded base register takes the loaded value rather than the written back address */
+        if w && !(l && get_bit(&reg_list, rn_reg as u32)) {
+            self.registers[rn_reg] = if u {base.wrapping_add(size)} else {base.wrapping_sub(size)};
+        }
+        self.cycles += reg_list.count_ones() as u64 + if l {BLOCK_LOAD_CYCLES} else {BLOCK_STORE_CYCLES};
+        Ok(())
+    }
+
+    fn multiple_instruction(&mut self, instruction : &u32) -> Result<(), RuntimeError> {
+        let rd_reg = get_bits(instruction, 16, 4) as usize;
+        let rm_reg = get_bits(instruction, 0, 4) as usize;
+        let rs_reg = get_bits(instruction, 8, 4) as usize;
+        let rn_reg = get_bits(instruction, 12, 4) as usize;
+
+        if rd_reg == rm_reg || rd_reg == PC || rm_reg == PC || rs_reg == PC ||  rn_reg == PC {return Err(RuntimeError::invalid(instruction, "Multiply instruction uses same register for Rd, Rm"))}
+
+        let a = get_bit(instruction, 21);
+        let s = get_bit(instruction, 20);
+
+        let rs_val = self.registers[rs_reg];
+        /* computed in 64 bits and truncated, as the hardware keeps only the low word */
+        let result = (self.registers[rm_reg] as u64 * rs_val as u64 + if a {self.registers[rn_reg] as u64} else {0}) as u32;
+        self.registers[rd_reg] = result;
+
+        if s {self.set_multiply_flags(result as u64, 32)}
+        self.cycles += MULTIPLY_CYCLES + multiply_cycles(rs_val) + if a {ACCUMULATE_CYCLES} else {0};
+        Ok(())
+    }
+
+    /* execute a long multiply (UMULL/UMLAL/SMULL/SMLAL), producing a 64 bit result in RdHi:RdLo.
+    Bit 22 selects signed operands, bit 21 accumulates the existing RdHi:RdLo value. */
+    fn long_multiply_instruction(&mut self, instruction : &u32) -> Result<(), RuntimeError> {
+        let rdhi_reg = get_bits(instruction, 16, 4) as usize;
+        let rdlo_reg = get_bits(instruction, 12, 4) as usize;
+        let rs_reg = get_bits(instruction, 8, 4) as usize;
+        let rm_reg = get_bits(instruction, 0, 4) as usize;
+
+        if [rdhi_reg, rdlo_reg, rs_reg, rm_reg].contains(&PC) {return Err(RuntimeError::invalid(instruction, "Long Multiply instruction uses PC"))}
+        if rdhi_reg == rdlo_reg || rdhi_reg == rm_reg || rdlo_reg == rm_reg {return Err(RuntimeError::invalid(instruction, "Long Multiply instruction uses same register for RdHi, RdLo, Rm"))}
+
+        let signed = get_bit(instruction, 22);
+        let a = get_bit(instruction, 21);
+        let s = get_bit(instruction, 20);
+
+        let (rm_val, rs_val) = (self.registers[rm_reg], self.registers[rs_reg]);
+        let product = if signed {(rm_val as i32 as i64 * rs_val as i32 as i64) as u64} else {rm_val as u64 * rs_val as u64};
+        let accumulator = (self.registers[rdhi_reg] as u64) << 32 | self.registers[rdlo_reg] as u64;
+        let result = if a {product.wrapping_add(accumulator)} else {product};
+
+        self.registers[rdhi_reg] = (result >> 32) as u32;
+        self.registers[rdlo_reg] = result as u32;
+
+        if s {self.set_multiply_flags(result, 64)}
+        self.cycles += LONG_MULTIPLY_CYCLES + multiply_cycles(rs_val) + if a {ACCUMULATE_CYCLES} else {0};
+        Ok(())
+    }
+
+    /* Update the flags for a multiply with the S bit set: N is the top bit and Z is set if the whole result is
+    zero, C and V are left unchanged (they are unpredictable on ARMv4, so are defined here as preserved)
+    result  <-  the result, zero extended from its width
+    width   <-  32 for MUL/MLA, 64 for the RdHi:RdLo result of a long multiply */
+    fn set_multiply_flags(&mut self, result : u64, width : u32) {
+        self.cpsr.n = result >> (width - 1) & 1 != 0;
+        self.cpsr.z = result == 0;
+    }
+
+    /* execute a QADD, QSUB, QDADD or QDSUB instruction (bits 21-22), adding Rn (doubled for QDADD/QDSUB) to or
+    subtracting it from Rm as signed values. Results outside the i32 range are clamped and set the Q flag. */
+    fn saturating_instruction(&mut self, instruction : &u32) -> Result<(), RuntimeError> {
+        let rn_reg = get_bits(instruction, 16, 4) as usize;
+        let rd_reg = get_bits(instruction, 12, 4) as usize;
+        let rm_reg = get_bits(instruction, 0, 4) as usize;
+        if [rn_reg, rd_reg, rm_reg].contains(&PC) {return Err(RuntimeError::invalid(instruction, "Saturating instruction uses PC"))}
+
+        let saturate = |val : i64| val.clamp(i32::MIN as i64, i32::MAX as i64);
+        let (rm_val, rn_val) = (self.registers[rm_reg] as i32 as i64, self.registers[rn_reg] as i32 as i64);
+        let double = get_bit(instruction, 22);
+        let doubled = if double {saturate(rn_val * 2)} else {rn_val};
+        let result = if get_bit(instruction, 21) {rm_val - doubled} else {rm_val + doubled};
+
+        if saturate(result) != result || (double && doubled != rn_val * 2) {self.cpsr.q = true}
+        self.registers[rd_reg] = saturate(result) as u32;
+        self.cycles += DATA_PROCESSING_CYCLES;
+        Ok(())
+    }
+
+    /* execute a CLZ instruction, writing the number of leading zero bits of Rm (32 for zero) into Rd */
+    fn clz_instruction(&mut self, instruction : &u32) -> Result<(), RuntimeError> {
+        let rd_reg = get_bits(instruction, 12, 4) as usize;
+        let rm_reg = get_bits(instruction, 0, 4) as usize;
+        if rd_reg == PC || rm_reg == PC {return Err(RuntimeError::invalid(instruction, "CLZ instruction uses PC as Rd or Rm"))}
+        self.registers[rd_reg] = self.registers[rm_reg].leading_zeros();
+        self.cycles += DATA_PROCESSING_CYCLES;
+        Ok(())
+    }
+
+    /* execute an MRS instruction, copying the CPSR into Rd */
+    fn mrs_instruction(&mut self, instruction : &u32) -> Result<(), RuntimeError> {
+        if get_bit(instruction, 22) {return Err(RuntimeError::invalid(instruction, "SPSR is not available"))}
+        let rd_reg = get_bits(instruction, 12, 4) as usize;
+        if rd_reg == PC {return Err(RuntimeError::invalid(instruction, "MRS instruction uses PC as Rd"))}
+        self.registers[rd_reg] = self.get_cpsr();
+        self.cycles += DATA_PROCESSING_CYCLES;
+        Ok(())
+    }
+
+    /* execute an MSR instruction, writing a register or rotated immediate into the CPSR.
+    Only the flags field (bit 19 of the field mask) holds state in this emulator. */
+    fn msr_instruction(&mut self, instruction : &u32) -> Result<(), RuntimeError> {
+        if get_bit(instruction, 22) {return Err(RuntimeError::invalid(instruction, "SPSR is not available"))}
+        let value = if get_bit(instruction, 25) {
+            get_bits(instruction, 0, 8).rotate_right(get_bits(instruction, 8, 4) * 2)
+        } else {self.registers[get_bits(instruction, 0, 4) as usize]};
+
+        /* the T bit cannot be changed by MSR */
+        if get_bit(instruction, 19) {self.set_cpsr(value & 0xF800_0000 | (self.thumb as u32) << THUMB_BIT)}
+        self.cycles += DATA_PROCESSING_CYCLES;
+        Ok(())
+    }
+
+    /* Set N and Z from a 32 bit result */
+    fn set_nz(&mut self, result : u32) {
+        self.cpsr.n = get_bit(&result, 31);
+        self.cpsr.z = result == 0;
+    }
+
+    /* Set the flags for result = a + b + carry: C is the carry out of the unsigned sum and V is set when the
+    signed sum does not fit in 32 bits, both computed in 64 bits
+    carry   <-  the carry in (ADC), false for ADD and CMN */
+    fn set_nzcv_for_add(&mut self, a : u32, b : u32, carry : bool, result : u32) {
+        self.set_nz(result);
+        self.cpsr.c = a as u64 + b as u64 + carry as u64 > u32::MAX as u64;
+        self.cpsr.v = a as i32 as i64 + b as i32 as i64 + carry as i64 != result as i32 as i64;
+    }
+
+    /* Set the flags for result = a - b - NOT carry: C is set when there is no unsigned borrow (a >= b + NOT carry,
+    so a >= b for SUB and CMP) and V when the signed difference does not fit in 32 bits, both computed in 64 bits
+    carry   <-  the carry in (SBC, RSC), true for SUB, RSB and CMP */
+    fn set_nzcv_for_sub(&mut self, a : u32, b : u32, carry : bool, result : u32) {
+        self.set_nz(result);
+        self.cpsr.c = a as u64 >= b as u64 + !carry as u64;
+        self.cpsr.v = a as i32 as i64 - b as i32 as i64 - !carry as i64 != result as i32 as i64;
+    }
+
+    fn process_data_instruction(&mut self, instruction : &u32) -> Result<(), RuntimeError> {
+        let opcode = get_bits(instruction, 21, 4);
+        let rd_reg = get_bits(instruction, 12, 4) as usize;
+        let rn_val = self.registers[get_bits(instruction, 16, 4) as usize];
+
+        /* the test operations exist only to set flags, so they ignore Rd (should be zero) and the S bit */
+        let test = matches!(opcode, CMP | CMN | TEQ | TST);
+        let i = get_bit(instruction, 25);
+        let s = get_bit(instruction, 20) || test;
+
+        let (operand_2_value, carryout) = if i {
+            /* the carry out of a rotated immediate is bit 31 of the result, an unrotated immediate leaves it unchanged */
+            let rotate = get_bits(instruction, 8, 4) << 1;
+            let immediate = get_bits(instruction, 0, 8).rotate_right(rotate);
+            (immediate, if rotate > 0 {get_bit(&immediate, 31)} else {self.cpsr.c})
+        } else {self.shift_operation(instruction)?};
+
+        let carry_in = self.cpsr.c as u32;
+        let result = match opcode {
+            TST | AND => rn_val & operand_2_value,
+            TEQ | EOR => rn_val ^ operand_2_value,
+            CMP | SUB => rn_val.wrapping_sub(operand_2_value),
+            RSB => operand_2_value.wrapping_sub(rn_val),
+            ADD | CMN => rn_val.wrapping_add(operand_2_value),
+            ADC => rn_val.wrapping_add(operand_2_value).wrapping_add(carry_in),
+            SBC => rn_val.wrapping_sub(operand_2_value).wrapping_sub(1 - carry_in),
+            RSC => operand_2_value.wrapping_sub(rn_val).wrapping_sub(1 - carry_in),
+            ORR => rn_val | operand_2_value,
+            MOV => operand_2_value,
+            BIC => rn_val & !operand_2_value,
+            MVN => !operand_2_value,
+            _ => return Err(RuntimeError::invalid(instruction, "Invalid operation in instruction"))
+        };
+
+        /* writing the PC branches, refilling the pipeline from the result */
+        if !test {self.write_register(rd_reg, result)}
+        if !test && rd_reg == PC {self.cycles += BRANCH_CYCLES}
+
+        /* with Rd == PC the S bit restores the CPSR from the SPSR (e.g. movs pc, lr to return from a handler)
+        instead of setting the flags from the result, without an SPSR the CPSR is left unchanged */
+        if s && !test && rd_reg == PC {
+            match self.spsr {
+                Some(spsr) => self.cpsr = spsr,
+                None => if self.trace {writeln!(self.trace_output, "            no SPSR to restore, CPSR unchanged").ok();}
+            }
+        } else if s {
+            let carry = carry_in == 1;
+            match opcode {
+                ADD | CMN => self.set_nzcv_for_add(rn_val, operand_2_value, false, result),
+                ADC => self.set_nzcv_for_add(rn_val, operand_2_value, carry, result),
+                SUB | CMP => self.set_nzcv_for_sub(rn_val, operand_2_value, true, result),
+                SBC => self.set_nzcv_for_sub(rn_val, operand_2_value, carry, result),
+                RSB => self.set_nzcv_for_sub(operand_2_value, rn_val, true, result),
+                RSC => self.set_nzcv_for_sub(operand_2_value, rn_val, carry, result),
+                /* logical operations take the carry from the shifter and leave V unchanged */
+                _ => {
+                    self.cpsr.c = carryout;
+                    self.set_nz(result);
+                }
+            }
+        }
+        self.cycles += DATA_PROCESSING_CYCLES + if !i && get_bit(instruction, 4) {REGISTER_SHIFT_CYCLES} else {0};
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Create a small machine with the given condition flags */
+    fn with_flags(n : bool, z : bool, c : bool, v : bool) -> CPU {
+        let mut cpu = CPU::with_memory(0x100);
+        cpu.set_flags(n, z, c, v);
+        cpu
+    }
+
+    #[test]
+    fn condition_is_read_from_bits_28_to_31() {
+        let cpu = with_flags(false, true, false, false);
+        /* the other bits of the word do not affect the condition */
+        assert!(cpu.check_condition(&(EQ << 28 | 0x0FFF_FFFF)));
+        assert!(cpu.check_condition(&(EQ << 28)));
+        assert!(!cpu.check_condition(&(NE << 28 | 0x0FFF_FFFF)));
+        assert!(cpu.check_condition(&(AL << 28 | 1)));
+        assert!(!cpu.check_condition(&(0xF << 28)));
+    }
+
+    #[test]
+    fn condition_gates_execution() {
+        let mut cpu = with_flags(false, true, false, false);
+        /* moveq r0, #1 runs with Z set, movne r1, #1 is skipped */
+        cpu.arm_instruction(&(EQ << 28 | 0x03A0_0001)).unwrap();
+        cpu.arm_instruction(&(NE << 28 | 0x03A0_1001)).unwrap();
+        assert_eq!((cpu.registers[0], cpu.registers[1]), (1, 0));
+    }
+
+    #[test]
+    fn words_are_stored_little_endian_on_any_host() {
+        let mut cpu = CPU::with_memory(0x100);
+        cpu.set_mem_word(0x10, 0x1234_5678).unwrap();
+        assert_eq!(cpu.memory[0x10..0x14], [0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(cpu.get_mem_word(0x10), Ok(0x1234_5678));
+    }
+
+    /* Execute a single instruction at address 0 of a fresh machine */
+    fn execute(instruction : u32) -> Result<StepResult, RuntimeError> {
+        let mut cpu = CPU::with_memory(0x100);
+        cpu.poke_words(&[(0, instruction)]).unwrap();
+        cpu.step()
+    }
+
+    #[test]
+    fn malformed_instructions_are_reported_as_errors() {
+        /* mul pc, r1, r2 */
+        assert!(matches!(execute(0xE00F_0291), Err(RuntimeError::InvalidInstruction {instruction : 0xE00F_0291, ..})));
+        /* ldr r0, [r1, #0x200] past the end of memory */
+        assert_eq!(execute(0xE591_0200), Err(RuntimeError::OutOfBounds {addr : 0x200}));
+        /* ldr r0, [r1, #2] */
+        assert_eq!(execute(0xE591_0002), Err(RuntimeError::UnalignedAccess {addr : 2}));
+    }
+
+    #[test]
+    fn unaligned_word_accesses_fault() {
+        let mut cpu = CPU::with_memory(0x100);
+        assert_eq!(cpu.get_mem_word(0x2), Err(RuntimeError::UnalignedAccess {addr : 0x2}));
+        assert_eq!(cpu.set_mem_word(0x2, 1), Err(RuntimeError::UnalignedAccess {addr : 0x2}));
+        /* str r0, [r1, #2] */
+        assert_eq!(execute(0xE581_0002), Err(RuntimeError::UnalignedAccess {addr : 0x2}));
+    }
+
+    #[test]
+    fn word_store_past_the_end_of_memory_faults() {
+        let mut cpu = CPU::new();
+        assert_eq!(cpu.set_mem_word(MEMSIZE - 2, 0xFFFF_FFFF), Err(RuntimeError::OutOfBounds {addr : MEMSIZE - 2}));
+        assert_eq!(cpu.set_mem_word(MEMSIZE, 0xFFFF_FFFF), Err(RuntimeError::OutOfBounds {addr : MEMSIZE}));
+        assert!(cpu.memory.iter().all(|byte| *byte == 0));
+        assert_eq!(cpu.set_mem_word(MEMSIZE - 4, 1), Ok(()));
+    }
+
+    #[test]
+    fn comparisons_set_flags_without_the_s_bit() {
+        let mut cpu = CPU::with_memory(0x100);
+        cpu.registers[1] = 3;
+        cpu.registers[2] = 3;
+        /* cmp r1, r2 and tst r1, #4 with the S bit clear, both still classified as data processing */
+        cpu.poke_words(&[(0x0, 0xE141_0002), (0x4, 0xE301_0004), (0x8, HALT)]).unwrap();
+        assert_eq!(instruction_type(&0xE141_0002), InstructionType::DataProcessing);
+        assert_eq!(instruction_type(&0xE301_0004), InstructionType::DataProcessing);
+        assert_eq!(cpu.step(), Ok(StepResult::Continued));
+        assert!(cpu.cpsr.z && cpu.cpsr.c);
+        cpu.set_flags(false, false, false, false);
+        assert_eq!(cpu.step(), Ok(StepResult::Continued));
+        assert!(cpu.cpsr.z);
+        assert_eq!(cpu.step(), Ok(StepResult::Halted));
+    }
+
+    #[test]
+    fn comparisons_ignore_rd() {
+        let mut cpu = CPU::with_memory(0x100);
+        cpu.registers[1] = 1;
+        /* cmp r1, #2 with Rd = pc, which must not branch */
+        cpu.poke_words(&[(0x0, 0xE351_F002), (0x4, HALT)]).unwrap();
+        assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+        assert!(cpu.cpsr.n && !cpu.cpsr.c);
+        assert_eq!(cpu.coverage(), vec![0x0, 0x4]);
+    }
+
+    #[test]
+    fn register_names_use_aliases() {
+        assert_eq!(reg_name(0), "r0");
+        assert_eq!(reg_name(12), "r12");
+        assert_eq!((reg_name(13), reg_name(14), reg_name(15)), ("sp", "lr", "pc"));
+        assert_eq!(reg_name(16), "r?");
+        assert_eq!(reg_name(u32::MAX), "r?");
+    }
+
+    #[test]
+    fn register_names_parse_case_insensitively() {
+        assert_eq!(parse_reg("r0"), Some(0));
+        assert_eq!(parse_reg("R15"), Some(15));
+        assert_eq!((parse_reg("SP"), parse_reg("lr"), parse_reg("Pc")), (Some(13), Some(14), Some(15)));
+        for n in 0..16 {assert_eq!(parse_reg(reg_name(n)), Some(n))}
+    }
+
+    #[test]
+    fn invalid_register_names_do_not_parse() {
+        for name in ["", "r", "r16", "r-1", "x1", "fp", "r1x", "sp1"] {assert_eq!(parse_reg(name), None, "{}", name)}
+    }
+
+    #[test]
+    fn random_instructions_never_panic() {
+        /* xorshift32 with a fixed seed, so a failing word can be reproduced */
+        let mut state : u32 = 0x2545_F491;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        let mut cpu = CPU::with_memory(0x100);
+        for _ in 0..100_000 {
+            cpu.reset();
+            let instruction = next();
+            let mut regs = [0; 16];
+            /* small values keep most transfers in memory, some registers are left large */
+            for reg in regs.iter_mut().take(PC) {*reg = if next() & 1 == 0 {next() & 0xFC} else {next()}}
+            regs[PC] = 0;
+            cpu.set_registers(regs);
+            cpu.set_cpsr(next() & 0xF800_0000);
+            cpu.poke_words(&[(0, instruction), (4, next())]).unwrap();
+            if next() & 7 == 0 {cpu.set_thumb(true)}
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cpu.step()));
+            assert!(result.is_ok(), "instruction {:#010x} panicked", instruction);
+        }
+    }
+
+    #[test]
+    fn unknown_instruction_message_breaks_down_the_fields() {
+        let message = RuntimeError::UnknownInstruction {instruction : 0xE7F0_00F0}.to_string();
+        assert!(message.starts_with("Invalid instruction type: 0xe7f000f0 (cond 1110, bits 27-25 011, bits 24-20 11111, bits 7-4 1111"), "{}", message);
+        assert!(message.contains("bits 27-25 are 011 with bit 4 set"), "{}", message);
+    }
+
+    #[test]
+    fn word_access_byte_layout_is_unchanged() {
+        let mut cpu = CPU::with_memory(0x10);
+        for (loc, byte) in [0x01, 0x23, 0x45, 0x67].iter().enumerate() {cpu.set_mem_byte(0x8 + loc, *byte).unwrap()}
+        assert_eq!(cpu.get_mem_word(0x8), Ok(0x6745_2301));
+        /* the last word of memory is accessible and its neighbours are untouched */
+        cpu.set_mem_word(0xC, 0x89AB_CDEF).unwrap();
+        assert_eq!(cpu.memory, [0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x23, 0x45, 0x67, 0xEF, 0xCD, 0xAB, 0x89]);
+        assert_eq!((cpu.get_mem_byte(0xC), cpu.get_mem_byte(0xF)), (0xEF, 0x89));
+        assert_eq!(cpu.get_mem_word(0x10), Err(RuntimeError::OutOfBounds {addr : 0x10}));
+    }
+
+    #[test]
+    fn registers_0_to_15_construct_and_16_fails() {
+        for n in 0..16 {assert_eq!(Register::try_from(n).map(Register::index), Ok(n as usize))}
+        assert_eq!(Register::try_from(16), Err(InvalidRegister(16)));
+        assert_eq!(Register::try_from(u32::MAX), Err(InvalidRegister(u32::MAX)));
+        assert_eq!((Register::SP.index(), Register::LR.index(), Register::PC.index()), (SP, LR, PC));
+    }
+
+    #[test]
+    fn poked_words_read_back() {
+        let mut cpu = CPU::with_memory(0x20);
+        assert_eq!(cpu.poke_words(&[(0x0, 0x1234_5678), (0x8, 0xDEAD_BEEF), (0x1C, 0xFFFF_FFFF)]), Ok(()));
+        assert_eq!(cpu.get_mem_word(0x0), Ok(0x1234_5678));
+        assert_eq!(cpu.get_mem_word(0x4), Ok(0));
+        assert_eq!(cpu.get_mem_word(0x8), Ok(0xDEAD_BEEF));
+        assert_eq!(cpu.get_mem_word(0x1C), Ok(0xFFFF_FFFF));
+    }
+
+    #[test]
+    fn poking_stops_at_the_first_bad_entry() {
+        let mut cpu = CPU::with_memory(0x20);
+        assert_eq!(cpu.poke_words(&[(0x0, 1), (0x6, 2), (0x8, 3)]), Err(RuntimeError::UnalignedAccess {addr : 0x6}));
+        assert_eq!((cpu.get_mem_word(0x0), cpu.get_mem_word(0x4), cpu.get_mem_word(0x8)), (Ok(1), Ok(0), Ok(0)));
+        assert_eq!(cpu.poke_words(&[(0x20, 1)]), Err(RuntimeError::OutOfBounds {addr : 0x20}));
+    }
+
+    #[test]
+    fn special_addresses_are_named() {
+        assert_eq!(special_address_name(0x2020_0000), Some("GPIO pin bank 0"));
+        assert_eq!(special_address_name(0x2020_0014), Some("GPIO pin bank 5"));
+        assert_eq!(special_address_name(0x2020_001C), Some("GPIO SET"));
+        assert_eq!(special_address_name(0x2020_0020), Some("GPIO SET (pins 32-53)"));
+        assert_eq!(special_address_name(0x2020_0028), Some("GPIO CLEAR"));
+        assert_eq!(special_address_name(0x2020_1000), Some("UART DATA"));
+        /* unaligned and ordinary addresses have no name */
+        assert_eq!(special_address_name(0x2020_102C), None);
+        assert_eq!(special_address_name(0x2020_0002), None);
+        assert_eq!(special_address_name(0x100), None);
+    }
+}
@@ -0,0 +1,191 @@
+use std::{convert::TryFrom, io::{self, BufRead, Write}};
+
+use crate::{CPU, Endian, Register, StepResult, RuntimeError, PC, parse_reg, reg_name, asm::parse_number, disasm::disassemble_at, thumb::{translate, describe}};
+
+// NAMED CONSTANTS============================================================
+const PROMPT : &str = "> ";
+
+const HELP : &str = "Commands:
+  load <file>       load a program and reset the machine
+  reg [rN]          show all registers, or one
+  set <rN> <value>  set a register
+  mem <addr> [n]    show n words of memory (default 1)
+  dis <start> <end> disassemble the words from start up to end
+  break <addr>      set a breakpoint (delete <addr> removes it)
+  step [n]          execute n instructions (default 1)
+  run               continue until the program halts or reaches a breakpoint
+  quit              leave the console";
+
+// INTERACTIVE CONSOLE==========================================================
+/* Read commands from input until quit or the end of input, printing each reply. Execution starts from the
+program's entry point.
+cpu     <-  the machine to drive
+input   <-  one command per line
+output  <-  where prompts and replies are written */
+pub fn run<R : BufRead, W : Write>(cpu : &mut CPU, input : &mut R, output : &mut W) -> io::Result<()> {
+    cpu.write_register(PC, cpu.entry);
+    let mut line = String::new();
+    loop {
+        write!(output, "{}", PROMPT)?;
+        output.flush()?;
+        line.clear();
+        if input.read_line(&mut line)? == 0 {return Ok(())}
+        match dispatch(cpu, &line) {
+            Some(reply) if reply.is_empty() => (),
+            Some(reply) => writeln!(output, "{}", reply)?,
+            None => return Ok(())
+        }
+    }
+}
+
+/* Execute a single console command
+line    <-  the command and its arguments separated by whitespace, e.g. "set r1 10"
+return  <-  the text to show (an error message for invalid commands), or None for quit */
+pub fn dispatch(cpu : &mut CPU, line : &str) -> Option<String> {
+    let words : Vec<&str> = line.split_whitespace().collect();
+    let reply = match words.as_slice() {
+        [] => Ok(String::new()),
+        ["quit"] | ["q"] | ["exit"] => return None,
+        ["help"] => Ok(HELP.to_string()),
+        ["load", file] => {
+            cpu.reset();
+            cpu.load_program(file)
+        }.map(|_| {
+            cpu.write_register(PC, cpu.entry);
+            format!("Loaded {}", file)
+        }).map_err(|err| err.to_string()),
+        ["reg"] => Ok(registers(cpu)),
+        ["reg", reg] => register(reg).map(|reg| format!("{:<4}: {val:>10} ({val:#010x})", reg.to_string(), val = value(cpu, reg.index()))),
+        ["set", reg, val] => register(reg).and_then(|reg| {
+            cpu.set_register(reg, number(val)?);
+            Ok(format!("{} = {:#010x}", reg, value(cpu, reg.index())))
+        }),
+        ["mem", addr] => memory(cpu, addr, "1"),
+        ["mem", addr, count] => memory(cpu, addr, count),
+        ["dis", start, end] => number(start).and_then(|start| Ok(cpu.disassemble_range(start as usize, number(end)? as usize).join("\n"))),
+        ["break", addr] => number(addr).map(|addr| {
+            cpu.add_breakpoint(addr);
+            format!("Breakpoint at {:#010x}", addr)
+        }),
+        ["delete", addr] => number(addr).map(|addr| {
+            if cpu.remove_breakpoint(addr) {format!("Removed breakpoint at {:#010x}", addr)} else {format!("No breakpoint at {:#010x}", addr)}
+        }),
+        ["step"] => Ok(step(cpu, 1)),
+        ["step", count] => number(count).map(|count| step(cpu, count)),
+        ["run"] => {
+            let result = cpu.resume();
+            Ok(stopped(cpu, result))
+        },
+        _ => Err(format!("Unknown command: {} (try help)", line.trim()))
+    };
+    Some(reply.unwrap_or_else(|err| format!("Error: {}", err)))
+}
+
+/* Format every register and the CPSR, one per line */
+fn registers(cpu : &CPU) -> String {
+    let mut lines : Vec<String> = (0..16).map(|reg| format!("{:<4}: {val:>10} ({val:#010x})", reg_name(reg), val = value(cpu, reg as usize))).collect();
+    lines.push(format!("cpsr: {val:>10} ({val:#010x})", val = cpu.get_cpsr()));
+    lines.join("\n")
+}
+
+/* Get a register's value, the PC is shown as the address of the next instruction (as gdb reports it) rather
+than the fetch address running ahead in the pipeline */
+fn value(cpu : &CPU, reg : usize) -> u32 {if reg == PC {cpu.next_address()} else {cpu.registers[reg]}}
+
+/* Show count words of memory starting at addr, one per line in memory byte order */
+fn memory(cpu : &CPU, addr : &str, count : &str) -> Result<String, String> {
+    let (addr, count) = (number(addr)? as usize, number(count)? as usize);
+    let lines : Result<Vec<String>, RuntimeError> = (0..count).map(|ind| {
+        let loc = addr.saturating_add(ind * 4);
+        cpu.read_word(loc, Endian::Little).map(|val| format!("{:#010x}: {:#010x}", loc, val.swap_bytes()))
+    }).collect();
+    lines.map(|lines| lines.join("\n")).map_err(|err| err.to_string())
+}
+
+/* Execute up to count instructions, stopping early when the program halts, a breakpoint is reached or an error occurs */
+fn step(cpu : &mut CPU, count : u32) -> String {
+    for ind in 0..count {
+        let next = cpu.next_address();
+        if ind > 0 && cpu.breakpoints.contains(&next) {return stopped(cpu, Ok(StepResult::Breakpoint(next)))}
+        match cpu.step() {
+            Ok(StepResult::Continued) | Ok(StepResult::Branched) => (),
+            result => return stopped(cpu, result)
+        }
+    }
+    stopped(cpu, Ok(StepResult::Continued))
+}
+
+/* Describe where execution stopped, showing the next instruction while the program can continue */
+fn stopped(cpu : &CPU, result : Result<StepResult, RuntimeError>) -> String {
+    let next = cpu.next_address();
+    let at = || match cpu.fetch(next) {
+        Ok(instruction) if cpu.thumb => format!("{:#010x}: {}", next, translate(instruction as u16).map_or(String::from("unsupported"), |op| describe(&op, next))),
+        Ok(word) => format!("{:#010x}: {}", next, disassemble_at(&word, next)),
+        Err(err) => err.to_string()
+    };
+    match result {
+        Ok(StepResult::Halted) => String::from("Program halted"),
+        Ok(StepResult::Breakpoint(addr)) => format!("Breakpoint at {:#010x}\n{}", addr, at()),
+        Ok(StepResult::Watchpoint {addr, kind}) => format!("Watchpoint ({:?}) on {:#010x}\n{}", kind, addr, at()),
+        Ok(_) => at(),
+        Err(err) => format!("Error: {}", err)
+    }
+}
+
+/* Parse a register name for a command */
+fn register(name : &str) -> Result<Register, String> {
+    parse_reg(name).and_then(|reg| Register::try_from(reg).ok()).ok_or(format!("Invalid register: {}", name))
+}
+
+/* Parse a decimal or 0x prefixed hexadecimal argument */
+fn number(text : &str) -> Result<u32, String> {parse_number(text).ok_or(format!("Invalid number: {}", text))}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    /* A machine holding a short program: mov r0, #5; add r0, r0, #1; halt */
+    fn machine() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load_program_from_reader(&mut &assemble("mov r0, #5\nadd r0, r0, #1\nhalt\n").unwrap()[..]).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn dispatches_scripted_commands() {
+        let mut cpu = machine();
+        assert_eq!(dispatch(&mut cpu, "set r1 10").as_deref(), Some("r1 = 0x0000000a"));
+        assert_eq!(dispatch(&mut cpu, "reg r1").as_deref(), Some("r1  :         10 (0x0000000a)"));
+        assert_eq!(dispatch(&mut cpu, "step").as_deref(), Some("0x00000004: add r0, r0, #1"));
+        assert_eq!(cpu.registers[0], 5);
+        /* the pc is the next instruction shown by step, not the fetch address */
+        assert_eq!(dispatch(&mut cpu, "reg pc").as_deref(), Some("pc  :          4 (0x00000004)"));
+        assert_eq!(dispatch(&mut cpu, "set pc 0x4").as_deref(), Some("pc = 0x00000004"));
+        assert_eq!(dispatch(&mut cpu, "reg pc").as_deref(), Some("pc  :          4 (0x00000004)"));
+        assert_eq!(dispatch(&mut cpu, "run").as_deref(), Some("Program halted"));
+        assert_eq!(cpu.registers[0], 6);
+        /* words are shown in memory byte order */
+        assert_eq!(dispatch(&mut cpu, "mem 0x0").as_deref(), Some("0x00000000: 0x0500a0e3"));
+        assert_eq!(dispatch(&mut cpu, "dis 0x0 0x8").as_deref(), Some("mov r0, #5\nadd r0, r0, #1"));
+        assert_eq!(dispatch(&mut cpu, "quit"), None);
+    }
+
+    #[test]
+    fn reports_invalid_commands() {
+        let mut cpu = machine();
+        assert_eq!(dispatch(&mut cpu, "").as_deref(), Some(""));
+        assert_eq!(dispatch(&mut cpu, "frobnicate").as_deref(), Some("Error: Unknown command: frobnicate (try help)"));
+        assert_eq!(dispatch(&mut cpu, "set r99 1").as_deref(), Some("Error: Invalid register: r99"));
+        assert_eq!(dispatch(&mut cpu, "mem zz").as_deref(), Some("Error: Invalid number: zz"));
+    }
+
+    #[test]
+    fn console_stops_at_quit() {
+        let mut cpu = machine();
+        let mut output = Vec::new();
+        run(&mut cpu, &mut "break 0x4\nrun\nquit\nstep\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "> Breakpoint at 0x00000004\n> Breakpoint at 0x00000004\n0x00000004: add r0, r0, #1\n> ");
+        assert_eq!(cpu.registers[0], 5);
+    }
+}
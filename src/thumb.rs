@@ -0,0 +1,188 @@
+use crate::{disasm, asm::CONDITIONS, SP, LR, PC};
+
+// NAMED CONSTANTS============================================================
+/* condition field of translated instructions (always) */
+const AL : u32 = 14 << 28;
+
+/* ARM data processing opcodes used by the translations */
+const SUB : u32 = 2;
+const RSB : u32 = 3;
+const ADD : u32 = 4;
+const CMP : u32 = 10;
+const MOV : u32 = 13;
+
+/* ARM bx with Rm zero */
+const BX : u32 = 0x012F_FF10;
+
+/* ARM immediate operand flag (bit 25) and the rotation placing an 8 bit immediate at bits 2-9 (ror 30) */
+const IMMEDIATE : u32 = 1 << 25;
+const WORD_SCALED : u32 = 15 << 8;
+
+// THUMB TRANSLATION============================================================
+/* A Thumb instruction, either as an equivalent ARM instruction word or as one of the operations that depend on
+the halfword aligned Thumb PC and so cannot be expressed in ARM */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ThumbOp {
+    Arm(u32),
+    /* b{cond} to the PC (instruction address + 4) plus offset */
+    Branch {cond : u32, offset : i32},
+    /* first half of bl: LR = PC + offset */
+    LinkHigh {offset : i32},
+    /* second half of bl: branch to LR + offset, setting LR to the return address */
+    LinkLow {offset : u32},
+    /* ldr rd, [pc, #offset] and add rd, pc, #offset, with the PC rounded down to a word */
+    LoadLiteral {rd : usize, offset : u32},
+    AddPc {rd : usize, offset : u32},
+    /* muls rd, rs, executed directly as rd may equal rs, which ARM MUL forbids for Rd and Rm */
+    Multiply {rd : usize, rs : usize}
+}
+
+/* Translate a Thumb instruction into the ARM instruction with the same effect where one exists
+instruction <-  the Thumb halfword
+return      <-  the translation, or None for encodings that are not supported */
+pub(crate) fn translate(instruction : u16) -> Option<ThumbOp> {
+    let word = instruction as u32;
+    let bits = |start : u32, n : u32| (word >> start) & ((1 << n) - 1);
+    let (rd, rs, rn) = (bits(0, 3), bits(3, 3), bits(6, 3));
+    let dp = |opcode : u32, s : bool, rn : u32, rd : u32, operand : u32| ThumbOp::Arm(AL | opcode << 21 | (s as u32) << 20 | rn << 16 | rd << 12 | operand);
+
+    Some(match bits(13, 3) {
+        /* add/subtract: adds/subs rd, rs, rn|#imm3 */
+        0b000 if bits(11, 2) == 0b11 => {
+            let operand = if bits(10, 1) == 1 {IMMEDIATE | rn} else {rn};
+            dp(if bits(9, 1) == 1 {SUB} else {ADD}, true, rs, rd, operand)
+        },
+        /* move shifted register: lsl/lsr/asr rd, rs, #imm5 -> movs rd, rs, <shift> #imm5 */
+        0b000 => dp(MOV, true, 0, rd, bits(6, 5) << 7 | bits(11, 2) << 5 | rs),
+        /* move/compare/add/subtract immediate: op rd, #imm8 */
+        0b001 => {
+            let (rd, imm) = (bits(8, 3), IMMEDIATE | bits(0, 8));
+            match bits(11, 2) {
+                0 => dp(MOV, true, 0, rd, imm),
+                1 => dp(CMP, true, rd, 0, imm),
+                2 => dp(ADD, true, rd, rd, imm),
+                _ => dp(SUB, true, rd, rd, imm)
+            }
+        },
+        0b010 => match bits(10, 3) {
+            0b000 => alu(bits(6, 4), rd, rs),
+            0b001 => hi_register(bits(8, 2), bits(7, 1) << 3 | rd, bits(6, 1) << 3 | rs),
+            0b010 | 0b011 => ThumbOp::LoadLiteral {rd : bits(8, 3) as usize, offset : bits(0, 8) * 4},
+            /* load/store with register offset: ldr/str{b} rd, [rb, ro] */
+            _ if bits(9, 1) == 0 => ThumbOp::Arm(transfer(bits(11, 1) == 1, bits(10, 1) == 1, rs, rd, IMMEDIATE | rn)),
+            /* load/store sign extended byte/halfword: strh, ldsb, ldrh, ldsh rd, [rb, ro] */
+            _ => {
+                let (load, signed, halfword) = match bits(10, 2) {
+                    0b00 => (false, false, true),
+                    0b01 => (true, true, false),
+                    0b10 => (true, false, true),
+                    _ => (true, true, true)
+                };
+                ThumbOp::Arm(halfword_transfer(load, signed, halfword, rs, rd, None, rn))
+            }
+        },
+        /* load/store with immediate offset: ldr/str{b} rd, [rb, #imm5] (scaled by 4 for words) */
+        0b011 => {
+            let byte = bits(12, 1) == 1;
+            ThumbOp::Arm(transfer(bits(11, 1) == 1, byte, rs, rd, bits(6, 5) * if byte {1} else {4}))
+        },
+        0b100 if bits(12, 1) == 0 => ThumbOp::Arm(halfword_transfer(bits(11, 1) == 1, false, true, rs, rd, Some(bits(6, 5) * 2), 0)),
+        /* sp relative load/store: ldr/str rd, [sp, #imm8 * 4] */
+        0b100 => ThumbOp::Arm(transfer(bits(11, 1) == 1, false, SP as u32, bits(8, 3), bits(0, 8) * 4)),
+        /* load address: add rd, pc|sp, #imm8 * 4 */
+        0b101 if bits(12, 1) == 0 && bits(11, 1) == 0 => ThumbOp::AddPc {rd : bits(8, 3) as usize, offset : bits(0, 8) * 4},
+        0b101 if bits(12, 1) == 0 => dp(ADD, false, SP as u32, bits(8, 3), IMMEDIATE | WORD_SCALED | bits(0, 8)),
+        /* add offset to stack pointer: add/sub sp, #imm7 * 4 */
+        0b101 if bits(8, 5) == 0b10000 => dp(if bits(7, 1) == 1 {SUB} else {ADD}, false, SP as u32, SP as u32, IMMEDIATE | WORD_SCALED | bits(0, 7)),
+        /* push {rlist, lr} -> stmdb sp!, and pop {rlist, pc} -> ldmia sp! */
+        0b101 if bits(9, 2) == 0b10 => {
+            let load = bits(11, 1) == 1;
+            let extra = if bits(8, 1) == 1 {1 << if load {PC} else {LR}} else {0};
+            ThumbOp::Arm(block_transfer(load, !load, SP as u32, bits(0, 8) | extra))
+        },
+        /* multiple load/store: ldmia/stmia rb!, {rlist} */
+        0b110 if bits(12, 1) == 0 => ThumbOp::Arm(block_transfer(bits(11, 1) == 1, false, bits(8, 3), bits(0, 8))),
+        /* software interrupt: swi #imm8 */
+        0b110 if bits(8, 4) == 0b1111 => ThumbOp::Arm(AL | 0b1111 << 24 | bits(0, 8)),
+        0b110 if bits(8, 4) == 0b1110 => return None,
+        /* conditional branch: b{cond} with a signed 8 bit halfword offset */
+        0b110 => ThumbOp::Branch {cond : bits(8, 4), offset : (bits(0, 8) as u8 as i8 as i32) << 1},
+        /* unconditional branch: b with a signed 11 bit halfword offset */
+        0b111 if bits(11, 2) == 0b00 => ThumbOp::Branch {cond : 14, offset : ((bits(0, 11) << 21) as i32) >> 20},
+        /* long branch with link, split over two instructions */
+        0b111 if bits(11, 2) == 0b10 => ThumbOp::LinkHigh {offset : ((bits(0, 11) << 21) as i32) >> 9},
+        0b111 if bits(11, 2) == 0b11 => ThumbOp::LinkLow {offset : bits(0, 11) << 1},
+        _ => return None
+    })
+}
+
+/* Translate a format 4 ALU operation on low registers, which always sets the flags
+op      <-  the 4 bit operation
+rd, rs  <-  destination (and first operand) and source registers */
+fn alu(op : u32, rd : u32, rs : u32) -> ThumbOp {
+    let dp = |opcode : u32, rn : u32, rd : u32, operand : u32| ThumbOp::Arm(AL | opcode << 21 | 1 << 20 | rn << 16 | rd << 12 | operand);
+    /* shifts by register: movs rd, rd, <shift> rs */
+    let shift = |kind : u32| dp(MOV, 0, rd, rs << 8 | kind << 5 | 1 << 4 | rd);
+    match op {
+        0b0010 => shift(0),
+        0b0011 => shift(1),
+        0b0100 => shift(2),
+        0b0111 => shift(3),
+        /* neg rd, rs -> rsbs rd, rs, #0 */
+        0b1001 => dp(RSB, rs, rd, IMMEDIATE),
+        0b1101 => ThumbOp::Multiply {rd : rd as usize, rs : rs as usize},
+        /* mvn rd, rs has no first operand */
+        0b1111 => dp(op, 0, rd, rs),
+        /* tst, cmp, cmn only set flags */
+        0b1000 | 0b1010 | 0b1011 => dp(op, rd, 0, rs),
+        /* and, eor, adc, sbc, orr, bic use the ARM opcode of the same number */
+        _ => dp(op, rd, rd, rs)
+    }
+}
+
+/* Translate a format 5 operation on the full register set (flags are only set by cmp)
+op      <-  add, cmp, mov, bx (0-3), bx ignores rd
+rd, rs  <-  the 4 bit register numbers including the H1/H2 bits */
+fn hi_register(op : u32, rd : u32, rs : u32) -> ThumbOp {
+    match op {
+        0 => ThumbOp::Arm(AL | ADD << 21 | rd << 16 | rd << 12 | rs),
+        1 => ThumbOp::Arm(AL | CMP << 21 | 1 << 20 | rd << 16 | rs),
+        2 => ThumbOp::Arm(AL | MOV << 21 | rd << 12 | rs),
+        _ => ThumbOp::Arm(AL | BX | rs)
+    }
+}
+
+/* Encode a pre-indexed ARM single data transfer without writeback
+offset  <-  12 bit immediate, or IMMEDIATE | rm for a register offset (the ARM I bit means a register here) */
+fn transfer(load : bool, byte : bool, rn : u32, rd : u32, offset : u32) -> u32 {
+    AL | 1 << 26 | 1 << 24 | 1 << 23 | (byte as u32) << 22 | (load as u32) << 20 | rn << 16 | rd << 12 | offset
+}
+
+/* Encode a pre-indexed ARM halfword transfer without writeback
+immediate   <-  the byte offset, or None to use the offset register rm */
+fn halfword_transfer(load : bool, signed : bool, halfword : bool, rn : u32, rd : u32, immediate : Option<u32>, rm : u32) -> u32 {
+    let offset = match immediate {
+        Some(imm) => 1 << 22 | (imm >> 4) << 8 | (imm & 0xF),
+        None => rm
+    };
+    AL | 1 << 24 | 1 << 23 | (load as u32) << 20 | rn << 16 | rd << 12 | 1 << 7 | (signed as u32) << 6 | (halfword as u32) << 5 | 1 << 4 | offset
+}
+
+/* Encode an ARM block transfer with writeback, incrementing after or decrementing before */
+fn block_transfer(load : bool, decrement_before : bool, rn : u32, list : u32) -> u32 {
+    AL | 0b100 << 25 | (decrement_before as u32) << 24 | (!decrement_before as u32) << 23 | 1 << 21 | (load as u32) << 20 | rn << 16 | list
+}
+
+/* Describe a translated instruction for the execution trace
+addr    <-  address of the Thumb instruction */
+pub(crate) fn describe(op : &ThumbOp, addr : u32) -> String {
+    match op {
+        ThumbOp::Arm(word) => disasm::disassemble_at(word, addr),
+        ThumbOp::Branch {cond, offset} => format!("b{} {:#x}", CONDITIONS.get(*cond as usize).filter(|_| *cond != 14).unwrap_or(&""), addr.wrapping_add(4).wrapping_add(*offset as u32)),
+        ThumbOp::LinkHigh {offset} => format!("bl (lr = pc + {:#x})", offset),
+        ThumbOp::LinkLow {offset} => format!("bl (lr + {:#x})", offset),
+        ThumbOp::LoadLiteral {rd, offset} => format!("ldr r{}, [pc, #{}]", rd, offset),
+        ThumbOp::AddPc {rd, offset} => format!("add r{}, pc, #{}", rd, offset),
+        ThumbOp::Multiply {rd, rs} => format!("muls r{}, r{}", rd, rs)
+    }
+}
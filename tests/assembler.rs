@@ -0,0 +1,56 @@
+mod common;
+
+use emulate::asm::assemble;
+use common::run;
+
+#[test]
+fn assembled_programs_execute() {
+    let cpu = run("mov r0, #42\nldr r1, =0xFF\nldr r2, =0x7FFC\nmul r3, r0, r1\nstr r3, [r2, #0]\nhalt");
+    assert_eq!((cpu.registers[0], cpu.registers[1], cpu.registers[2]), (42, 0xFF, 0x7FFC));
+    assert_eq!(cpu.registers[3], 42 * 0xFF);
+    assert_eq!(cpu.memory[0x7FFC..], (42u32 * 0xFF).to_le_bytes());
+}
+
+#[test]
+fn assembled_loops_execute() {
+    let cpu = run("mov r0, #0\nmov r1, #10\nloop: add r0, r0, r1\nsubs r1, r1, #1\nbne loop\nhalt");
+    assert_eq!(cpu.registers[0], 55);
+}
+
+#[test]
+fn assembled_transfers_execute() {
+    let cpu = run("mov r1, #0x100\nmov r0, #7\nstr r0, [r1]\nldr r2, [r1]\nstrb r0, [r1, #5]\nldrb r3, [r1, #5]\nhalt");
+    assert_eq!((cpu.registers[2], cpu.registers[3]), (7, 7));
+}
+
+#[test]
+fn unaligned_branch_targets_are_rejected() {
+    let err = assemble("b 0x6").unwrap_err();
+    assert_eq!(err.line, 1);
+    assert!(err.message.contains("not word aligned"));
+}
+
+#[test]
+fn out_of_range_branch_targets_are_rejected() {
+    assert!(assemble("b 0x4000000").unwrap_err().message.contains("out of range"));
+    assert!(assemble("mov r0, r0\nbl 0x200000C").unwrap_err().message.contains("out of range"));
+    /* the furthest target in each direction is accepted */
+    assert_eq!(assemble("b 0x2000004").unwrap(), 0xEA7F_FFFFu32.to_le_bytes());
+    assert!(assemble("mov r0, r0\nmov r0, r0\nb 0xFE000010").is_ok());
+}
+
+#[test]
+fn literal_loads_execute_from_the_pool() {
+    let cpu = run("ldr r0, =0x12345678\nldr r1, =0xFFFFFF00\nhalt");
+    assert_eq!((cpu.registers[0], cpu.registers[1]), (0x1234_5678, 0xFFFF_FF00));
+}
+
+#[test]
+fn literal_load_as_the_last_instruction_reads_the_pool() {
+    let binary = assemble("mov r0, r0\nldr r2, =0x12345678").unwrap();
+    let mut cpu = emulate::CPU::new();
+    cpu.load_program_from_reader(&mut &binary[..]).unwrap();
+    cpu.add_breakpoint(0x8);
+    assert_eq!(cpu.run_program(), Ok(emulate::StepResult::Breakpoint(0x8)));
+    assert_eq!(cpu.registers[2], 0x1234_5678);
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use emulate::{Register, RuntimeError, StepResult, MEMSIZE};
+use common::{load, run};
+
+#[test]
+fn forward_branch_lands_on_target() {
+    let mut cpu = load("mov r0, r0\nmov r0, r0\nb forward\nmov r1, #1\nforward: mov r2, #2\nhalt");
+    cpu.set_register(Register::PC, 8);
+    assert_eq!(cpu.step(), Ok(StepResult::Branched));
+    /* the next instruction executed is the target, skipping the one after the branch */
+    assert_eq!(cpu.step(), Ok(StepResult::Continued));
+    assert_eq!(cpu.coverage(), vec![0x8, 0x10]);
+    assert_eq!((cpu.registers[1], cpu.registers[2]), (0, 2));
+}
+
+#[test]
+fn backward_branch_lands_on_target() {
+    let mut cpu = load("mov r1, #1\nmov r0, r0\nb 0x0");
+    cpu.set_register(Register::PC, 8);
+    assert_eq!(cpu.step(), Ok(StepResult::Branched));
+    assert_eq!(cpu.step(), Ok(StepResult::Continued));
+    assert_eq!(cpu.coverage(), vec![0x0, 0x8]);
+    assert_eq!(cpu.registers[1], 1);
+}
+
+#[test]
+fn branches_skip_and_repeat_code() {
+    let cpu = run("mov r0, #3\nloop: add r1, r1, #2\nsub r0, r0, #1\ncmp r0, #0\nbne loop\nb end\nmov r2, #1\nend: halt");
+    assert_eq!((cpu.registers[0], cpu.registers[1], cpu.registers[2]), (0, 6, 0));
+}
+
+#[test]
+fn branch_with_link_saves_return_address() {
+    let cpu = run("mov r0, r0\nbl sub\nmov r2, #2\nhalt\nsub: mov r1, #1\nmov pc, lr");
+    assert_eq!(cpu.registers[14], 0x8);
+    assert_eq!((cpu.registers[1], cpu.registers[2]), (1, 2));
+}
+
+#[test]
+fn run_from_skips_the_instructions_before_the_entry() {
+    let mut cpu = load("mov r0, #1\nmov r1, #2\nentry: mov r2, #3\nhalt");
+    assert_eq!(cpu.run_from(0x8), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[0], cpu.registers[1], cpu.registers[2]), (0, 0, 3));
+    assert_eq!(cpu.coverage(), vec![0x8, 0xC]);
+}
+
+#[test]
+fn branch_before_memory_start_is_out_of_range() {
+    let mut cpu = load("b 0xFFFFFFF8");
+    assert_eq!(cpu.run_program(), Err(RuntimeError::BranchOutOfRange {target : 0xFFFF_FFF8}));
+}
+
+#[test]
+fn branch_past_the_end_of_memory_is_out_of_range() {
+    let mut cpu = load(&format!("mov r0, r0\nbl {:#x}", MEMSIZE));
+    assert_eq!(cpu.run_program(), Err(RuntimeError::BranchOutOfRange {target : MEMSIZE as u32}));
+    /* the last word of memory is a valid target */
+    let mut cpu = load(&format!("b {:#x}", MEMSIZE - 4));
+    cpu.poke_words(&[(MEMSIZE - 4, emulate::HALT)]).unwrap();
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+}
+
+#[test]
+fn bx_to_even_address_stays_in_arm() {
+    let mut cpu = load("mov r0, #0x10\nbx r0\nmov r1, #1\nmov r1, #2\nmov r2, #3\nhalt");
+    assert_eq!(cpu.step(), Ok(StepResult::Continued));
+    assert_eq!(cpu.step(), Ok(StepResult::Branched));
+    assert_eq!(cpu.get_cpsr() & 1 << 5, 0);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(cpu.coverage(), vec![0x0, 0x4, 0x10, 0x14]);
+    assert_eq!((cpu.registers[1], cpu.registers[2]), (0, 3));
+}
+
+#[test]
+fn bx_to_odd_address_enters_thumb() {
+    /* the target holds the Thumb halfwords movs r2, #3 and swi #1 */
+    let mut cpu = load("mov r0, #0x11\nbx r0\nmov r1, #1\nmov r1, #2\nhalt");
+    cpu.memory[0x10..0x14].copy_from_slice(&[0x03, 0x22, 0x01, 0xDF]);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_ne!(cpu.get_cpsr() & 1 << 5, 0);
+    assert_eq!(cpu.coverage(), vec![0x0, 0x4, 0x10, 0x12]);
+    assert_eq!((cpu.registers[1], cpu.registers[2]), (0, 3));
+}
@@ -0,0 +1,42 @@
+#![allow(dead_code)]
+
+use std::{cell::RefCell, io::{self, Write}, rc::Rc};
+
+use emulate::{CPU, StepResult, asm::assemble};
+
+/* Assemble a program and load it into a new machine at address 0 */
+pub fn load(source : &str) -> CPU {
+    let binary = assemble(source).unwrap();
+    let mut cpu = CPU::new();
+    cpu.load_program_from_reader(&mut &binary[..]).unwrap();
+    cpu
+}
+
+/* Assemble a program and run it until it halts */
+pub fn run(source : &str) -> CPU {
+    let mut cpu = load(source);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    cpu
+}
+
+/* Load raw instruction words (for encodings the assembler does not support) from address 0, followed by a halt */
+pub fn load_words(words : &[u32]) -> CPU {
+    let mut cpu = CPU::new();
+    let entries : Vec<(usize, u32)> = words.iter().chain(&[emulate::HALT]).enumerate().map(|(ind, word)| (ind * 4, *word)).collect();
+    cpu.poke_words(&entries).unwrap();
+    cpu
+}
+
+/* An output sink that can still be read after a clone is given to the machine */
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    /* Get everything written so far */
+    pub fn contents(&self) -> String {String::from_utf8_lossy(&self.0.borrow()).into_owned()}
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf : &[u8]) -> io::Result<usize> {self.0.borrow_mut().write(buf)}
+    fn flush(&mut self) -> io::Result<()> {Ok(())}
+}
@@ -0,0 +1,179 @@
+mod common;
+
+use emulate::{CPU, StepResult};
+use common::load_words;
+
+/* Reference ARM condition table
+cond    <-  condition code 0-14
+flags   <-  (n, z, c, v) */
+fn expected(cond : u32, (n, z, c, v) : (bool, bool, bool, bool)) -> bool {
+    match cond {
+        0 => z,
+        1 => !z,
+        2 => c,
+        3 => !c,
+        4 => n,
+        5 => !n,
+        6 => v,
+        7 => !v,
+        8 => c && !z,
+        9 => !c || z,
+        10 => n == v,
+        11 => n != v,
+        12 => !z && n == v,
+        13 => z || n != v,
+        _ => true
+    }
+}
+
+#[test]
+fn every_condition_gates_against_every_flag_state() {
+    for flags in 0..16 {
+        let flags = (flags & 8 != 0, flags & 4 != 0, flags & 2 != 0, flags & 1 != 0);
+        for cond in 0..15 {
+            /* mov<cond> r0, #1 */
+            let mut cpu = load_words(&[cond << 28 | 0x03A0_0001]);
+            cpu.set_flags(flags.0, flags.1, flags.2, flags.3);
+            assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+            assert_eq!(cpu.registers[0] == 1, expected(cond, flags), "condition {} with flags {:?}", cond, flags);
+        }
+    }
+}
+
+/* mov r0, r0 */
+const NOP : u32 = 0xE1A0_0000;
+const EQ : u32 = 0;
+const NE : u32 = 1;
+
+/* Build a machine with Z set, r0 = 0x100, r1 = 5, r2 = 7 and 9 stored at 0x104, running the instruction at 0
+given the condition, followed by no-ops up to and including a branch target at 0x20
+instruction <-  the instruction with any condition, replaced by cond */
+fn machine(cond : u32, instruction : u32) -> CPU {
+    let mut words = vec![cond << 28 | instruction & 0x0FFF_FFFF];
+    words.resize(9, NOP);
+    let mut cpu = load_words(&words);
+    cpu.poke_words(&[(0x104, 9)]).unwrap();
+    cpu.registers[..3].copy_from_slice(&[0x100, 5, 7]);
+    cpu.set_flags(false, true, false, false);
+    cpu
+}
+
+/* Check an instruction with a failing condition (NE while Z is set) changes nothing but the PC */
+fn assert_skipped(instruction : u32) {
+    let mut cpu = machine(NE, instruction);
+    let before = cpu.checkpoint();
+    assert_eq!(cpu.step(), Ok(StepResult::Continued), "{:#010x}", instruction);
+    let diff = before.diff(&cpu.checkpoint());
+    assert!(diff.registers.iter().all(|(reg, _, _)| *reg == 15) && diff.cpsr.is_none() && diff.memory.is_empty(),
+        "{:#010x} changed:\n{}", instruction, diff);
+}
+
+/* Execute an instruction with a passing condition (EQ while Z is set) */
+fn executed(instruction : u32) -> CPU {
+    let mut cpu = machine(EQ, instruction);
+    assert!(cpu.step().is_ok(), "{:#010x}", instruction);
+    cpu
+}
+
+#[test]
+fn data_processing_respects_its_condition() {
+    /* add r3, r1, r2; cmp r1, r2 */
+    for instruction in [0xE081_3002, 0xE151_0002] {assert_skipped(instruction)}
+    assert_eq!(executed(0xE081_3002).registers[3], 12);
+    let cpu = executed(0xE151_0002);
+    assert!(cpu.cpsr.n && !cpu.cpsr.z);
+}
+
+#[test]
+fn data_transfers_respect_their_condition() {
+    /* str r1, [r0]; ldr r4, [r0, #4]!; strh r1, [r0, #8]; stmia r0!, {r1, r2} */
+    for instruction in [0xE580_1000, 0xE5B0_4004, 0xE1C0_10B8, 0xE8A0_0006] {assert_skipped(instruction)}
+    assert_eq!(executed(0xE580_1000).memory[0x100], 5);
+    let cpu = executed(0xE5B0_4004);
+    assert_eq!((cpu.registers[4], cpu.registers[0]), (9, 0x104));
+    assert_eq!(executed(0xE1C0_10B8).memory[0x108], 5);
+    let cpu = executed(0xE8A0_0006);
+    assert_eq!((cpu.memory[0x100], cpu.memory[0x104], cpu.registers[0]), (5, 7, 0x108));
+}
+
+#[test]
+fn multiplies_respect_their_condition() {
+    /* mul r3, r1, r2; umull r3, r4, r1, r2 */
+    for instruction in [0xE003_0291, 0xE084_3291] {assert_skipped(instruction)}
+    assert_eq!(executed(0xE003_0291).registers[3], 35);
+    let cpu = executed(0xE084_3291);
+    assert_eq!((cpu.registers[3], cpu.registers[4]), (35, 0));
+}
+
+#[test]
+fn branches_respect_their_condition() {
+    /* b 0x20; bl 0x20 */
+    for instruction in [0xEA00_0006, 0xEB00_0006] {assert_skipped(instruction)}
+    let mut cpu = executed(0xEA00_0006);
+    cpu.step().unwrap();
+    assert_eq!(cpu.coverage(), vec![0x0, 0x20]);
+    assert_eq!(executed(0xEB00_0006).registers[14], 0x4);
+    /* the skipped branch falls through to the next instruction */
+    let mut cpu = machine(NE, 0xEA00_0006);
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert_eq!(cpu.coverage(), vec![0x0, 0x4]);
+}
+
+#[test]
+fn software_interrupts_respect_their_condition() {
+    assert_skipped(emulate::HALT);
+    let mut cpu = machine(EQ, emulate::HALT);
+    assert_eq!(cpu.step(), Ok(StepResult::Halted));
+}
+
+/* Whether each condition holds after comparing a with b, from unsigned and signed arithmetic on the values
+add     <-  true for cmn (a + b), false for cmp (a - b) */
+fn compared(cond : u32, a : u32, b : u32, add : bool) -> bool {
+    let (result, carry, overflow) = if add {
+        (a.wrapping_add(b), a.checked_add(b).is_none(), (a as i32).checked_add(b as i32).is_none())
+    } else {
+        (a.wrapping_sub(b), a >= b, (a as i32).checked_sub(b as i32).is_none())
+    };
+    /* GE holds when the exact signed result is not negative */
+    let exact = a as i32 as i64 + if add {b as i32 as i64} else {-(b as i32 as i64)};
+    let signed_ge = exact >= 0;
+    match cond {
+        0 => result == 0,
+        1 => result != 0,
+        2 => carry,
+        3 => !carry,
+        4 => (result as i32) < 0,
+        5 => result as i32 >= 0,
+        6 => overflow,
+        7 => !overflow,
+        8 => carry && result != 0,
+        9 => !carry || result == 0,
+        10 => signed_ge,
+        11 => !signed_ge,
+        12 => signed_ge && result != 0,
+        13 => !signed_ge || result == 0,
+        _ => true
+    }
+}
+
+#[test]
+fn every_condition_after_cmp_and_cmn_corner_cases() {
+    const CASES : [(u32, u32); 9] = [
+        (1, 2), (2, 1), (5, 5), (0, 0),
+        (0x8000_0000, 1), (0x7FFF_FFFF, 0xFFFF_FFFF), (0, 0x8000_0000), (0xFFFF_FFFF, 1), (0x7FFF_FFFF, 1)
+    ];
+    for (a, b) in CASES.iter() {
+        /* cmp r0, r1 and cmn r0, r1 */
+        for (compare, add) in [(0xE150_0001, false), (0xE170_0001, true)].iter() {
+            for cond in 0..15 {
+                /* mov<cond> r2, #1 */
+                let mut cpu = load_words(&[*compare, cond << 28 | 0x03A0_2001]);
+                cpu.registers[0] = *a;
+                cpu.registers[1] = *b;
+                assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+                assert_eq!(cpu.registers[2] == 1, compared(cond, *a, *b, *add), "condition {} after {:#x} {} {:#x}", cond, a, if *add {"cmn"} else {"cmp"}, b);
+            }
+        }
+    }
+}
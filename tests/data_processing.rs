@@ -0,0 +1,265 @@
+mod common;
+
+use emulate::{Cpsr, RuntimeError, StepResult};
+use common::{load, load_words, run};
+
+/* Run a program with the carry flag set beforehand */
+fn run_with_carry(source : &str) -> emulate::CPU {
+    let mut cpu = load(source);
+    cpu.set_flags(false, false, true, false);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    cpu
+}
+
+#[test]
+fn mvn_inverts_operand() {
+    assert_eq!(run("mvn r0, #0xff\nhalt").registers[0], 0xffff_ff00);
+}
+
+#[test]
+fn bic_clears_operand_bits() {
+    assert_eq!(run("mov r1, #0xff\nbic r0, r1, #0x0f\nhalt").registers[0], 0xf0);
+}
+
+#[test]
+fn adc_adds_carry_in() {
+    assert_eq!(run_with_carry("mov r1, #5\nadc r0, r1, #3\nhalt").registers[0], 9);
+    assert_eq!(run("mov r1, #5\nadc r0, r1, #3\nhalt").registers[0], 8);
+}
+
+#[test]
+fn sbc_subtracts_borrow() {
+    assert_eq!(run_with_carry("mov r1, #5\nsbc r0, r1, #3\nhalt").registers[0], 2);
+    assert_eq!(run("mov r1, #5\nsbc r0, r1, #3\nhalt").registers[0], 1);
+}
+
+#[test]
+fn rsc_reverse_subtracts_borrow() {
+    assert_eq!(run_with_carry("mov r1, #3\nrsc r0, r1, #5\nhalt").registers[0], 2);
+    assert_eq!(run("mov r1, #3\nrsc r0, r1, #5\nhalt").registers[0], 1);
+}
+
+#[test]
+fn cmn_sets_flags_of_addition() {
+    let cpu = run("mvn r1, #0\ncmn r1, #1\nhalt");
+    assert_eq!(cpu.registers[1], 0xffff_ffff);
+    assert!(cpu.cpsr.z && cpu.cpsr.c && !cpu.cpsr.n && !cpu.cpsr.v);
+}
+
+#[test]
+fn subtraction_carry_is_not_borrow() {
+    /* equal values: no borrow */
+    let cpu = run("mov r0, #5\nsubs r1, r0, #5\nhalt");
+    assert!(cpu.cpsr.c && cpu.cpsr.z);
+    /* larger from smaller: borrow */
+    let cpu = run("mov r0, #3\ncmp r0, #5\nhalt");
+    assert!(!cpu.cpsr.c && cpu.cpsr.n);
+    /* smaller from larger: no borrow */
+    let cpu = run("mov r0, #5\ncmp r0, #3\nhalt");
+    assert!(cpu.cpsr.c && !cpu.cpsr.n);
+}
+
+#[test]
+fn reverse_subtraction_borrows_the_other_way() {
+    /* 5 - 3: no borrow */
+    let cpu = run("mov r0, #3\nrsbs r1, r0, #5\nhalt");
+    assert_eq!(cpu.registers[1], 2);
+    assert!(cpu.cpsr.c);
+    /* 3 - 5: borrow */
+    let cpu = run("mov r0, #5\nrsbs r1, r0, #3\nhalt");
+    assert_eq!(cpu.registers[1], 0xFFFF_FFFE);
+    assert!(!cpu.cpsr.c);
+}
+
+#[test]
+fn subtraction_wraps_instead_of_panicking() {
+    let cpu = run("mov r1, #1\nmov r2, #2\nsubs r0, r1, r2\nhalt");
+    assert_eq!(cpu.registers[0], 0xFFFF_FFFF);
+    assert!(cpu.cpsr.n && !cpu.cpsr.c && !cpu.cpsr.v && !cpu.cpsr.z);
+}
+
+#[test]
+fn signed_overflow_sets_v() {
+    /* 0x80000000 - 1 overflows to a positive number, 0x7fffffff + 1 to a negative one */
+    let cpu = run("mov r1, #0x80000000\nsubs r0, r1, #1\nhalt");
+    assert_eq!(cpu.registers[0], 0x7FFF_FFFF);
+    assert!(cpu.cpsr.v && cpu.cpsr.c);
+    let cpu = run("mvn r1, #0x80000000\nadds r0, r1, #1\nhalt");
+    assert_eq!(cpu.registers[0], 0x8000_0000);
+    assert!(cpu.cpsr.v && !cpu.cpsr.c && cpu.cpsr.n && !cpu.cpsr.z);
+}
+
+#[test]
+fn addition_wraps_with_carry() {
+    let cpu = run("mvn r1, #0\nadds r0, r1, #2\nhalt");
+    assert_eq!(cpu.registers[0], 1);
+    assert!(cpu.cpsr.c && !cpu.cpsr.v);
+}
+
+#[test]
+fn msr_flags_read_back_with_mrs() {
+    let cpu = run("mov r0, #0xA0000000\nmsr cpsr_f, r0\nmrs r1, cpsr\nhalt");
+    assert!(cpu.cpsr.n && !cpu.cpsr.z && cpu.cpsr.c && !cpu.cpsr.v);
+    assert_eq!(cpu.registers[1] & 0xF000_0000, 0xA000_0000);
+}
+
+#[test]
+fn mrs_into_pc_is_rejected() {
+    /* mrs pc, cpsr */
+    let mut cpu = load_words(&[0xE10F_F000]);
+    assert_eq!(cpu.validate().first().map(|warning| warning.reason), Some("MRS instruction uses PC as Rd"));
+    assert!(matches!(cpu.run_program(), Err(RuntimeError::InvalidInstruction {instruction : 0xE10F_F000, ..})));
+}
+
+#[test]
+fn add_carry_out_of_bit_31_sets_c_and_z() {
+    let cpu = run("mvn r1, #0\nadds r0, r1, #1\nhalt");
+    assert_eq!(cpu.registers[0], 0);
+    assert!(cpu.cpsr.c && cpu.cpsr.z && !cpu.cpsr.v && !cpu.cpsr.n);
+}
+
+#[test]
+fn rsb_overflow_sets_v() {
+    /* 0 - 0x80000000 overflows */
+    let cpu = run("mov r1, #0x80000000\nrsbs r0, r1, #0\nhalt");
+    assert_eq!(cpu.registers[0], 0x8000_0000);
+    assert!(cpu.cpsr.v && !cpu.cpsr.c);
+}
+
+#[test]
+fn pc_operand_reads_the_address_plus_8() {
+    let cpu = run("mov r0, r0\nmov r0, pc\nadd r1, pc, #4\nhalt");
+    assert_eq!(cpu.registers[0], 0x4 + 8);
+    assert_eq!(cpu.registers[1], 0x8 + 8 + 4);
+}
+
+#[test]
+fn pc_reads_follow_a_branch() {
+    /* the pipeline is refilled after the branch, so pc is relative to the target */
+    let cpu = run("b target\nmov r1, #1\nmov r1, #2\ntarget: mov r0, pc\nhalt");
+    assert_eq!((cpu.registers[0], cpu.registers[1]), (0xC + 8, 0));
+}
+
+#[test]
+fn rotated_immediate_carry_is_bit_31_of_the_result() {
+    /* 0xf0000000 is 0xf rotated, its bit 31 becomes the carry for a following conditional */
+    let cpu = run("movs r0, #0xF0000000\nmovcs r1, #1\nhalt");
+    assert!(cpu.cpsr.c && cpu.cpsr.n);
+    assert_eq!(cpu.registers[1], 1);
+    /* 0x3f0 is also rotated but bit 31 is clear */
+    let cpu = run("mov r0, #0\ncmp r0, #0\nmovs r0, #0x3F0\nmovcs r1, #1\nhalt");
+    assert!(!cpu.cpsr.c);
+    assert_eq!(cpu.registers[1], 0);
+}
+
+#[test]
+fn unrotated_immediate_leaves_carry_unchanged() {
+    let cpu = run("mov r0, #0\ncmp r0, #0\nmovs r0, #0xFF\nmovcs r1, #1\nhalt");
+    assert!(cpu.cpsr.c);
+    assert_eq!(cpu.registers[1], 1);
+}
+
+#[test]
+fn clz_counts_leading_zeros() {
+    let cpu = run("mov r0, #0\nclz r1, r0\nmov r0, #1\nclz r2, r0\nmov r0, #0x80000000\nclz r3, r0\nmov r0, #0x10000\nclz r4, r0\nhalt");
+    assert_eq!(cpu.registers[1..5], [32, 31, 0, 15]);
+}
+
+#[test]
+fn cmn_wrapping_to_zero_sets_z_and_c_without_writing_rd() {
+    /* mvn r1, #0; cmn r1, #1 with the Rd field set to r3 */
+    let mut cpu = load_words(&[0xE3E0_1000, 0xE371_3001]);
+    cpu.registers[3] = 0x77;
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert!(cpu.cpsr.z && cpu.cpsr.c && !cpu.cpsr.n && !cpu.cpsr.v);
+    assert_eq!((cpu.registers[1], cpu.registers[3]), (0xFFFF_FFFF, 0x77));
+}
+
+#[test]
+fn pc_as_rn_rm_and_shifted_rm_reads_the_address_plus_8() {
+    /* add r0, pc, #0 at 0x10; add r1, r2, pc at 0x14; add r3, r2, pc, lsl #1 at 0x18 */
+    let cpu = run("mov r2, #0\nmov r0, r0\nmov r0, r0\nmov r0, r0\nadd r0, pc, #0\nadd r1, r2, pc\nadd r3, r2, pc, lsl #1\nhalt");
+    assert_eq!(cpu.registers[0], 0x10 + 8);
+    assert_eq!(cpu.registers[1], 0x14 + 8);
+    assert_eq!(cpu.registers[3], (0x18 + 8) << 1);
+}
+
+#[test]
+fn mov_pc_redirects_to_the_register() {
+    let mut cpu = load("mov r0, #0x10\nmov pc, r0\nmov r1, #1\nmov r1, #2\nmov r2, #3\nhalt");
+    assert_eq!(cpu.step(), Ok(StepResult::Continued));
+    assert_eq!(cpu.step(), Ok(StepResult::Branched));
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(cpu.coverage(), vec![0x0, 0x4, 0x10, 0x14]);
+    assert_eq!((cpu.registers[1], cpu.registers[2]), (0, 3));
+}
+
+#[test]
+fn subs_pc_restores_the_saved_flags() {
+    let mut cpu = load("mov lr, #0x14\nsubs pc, lr, #4\nmov r1, #1\nmov r1, #2\nmov r2, #3\nhalt");
+    cpu.set_spsr(Some(Cpsr {n : true, z : false, c : true, v : false, q : false}));
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[1], cpu.registers[2]), (0, 3));
+    assert!(cpu.cpsr.n && cpu.cpsr.c && !cpu.cpsr.z);
+}
+
+#[test]
+fn movs_pc_copies_the_spsr_into_the_cpsr() {
+    let mut cpu = load("mov lr, #0xC\nmovs pc, lr\nmov r1, #1\nmov r2, #2\nhalt");
+    let spsr = Cpsr {n : false, z : true, c : false, v : true, q : true};
+    cpu.set_spsr(Some(spsr));
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(cpu.cpsr, spsr);
+    assert_eq!((cpu.registers[1], cpu.registers[2]), (0, 2));
+}
+
+#[test]
+fn movs_pc_without_an_spsr_leaves_the_cpsr() {
+    let mut cpu = load("mov lr, #0xC\nmovs pc, lr\nmov r1, #1\nmov r2, #2\nhalt");
+    cpu.set_flags(true, false, true, false);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(cpu.cpsr, Cpsr {n : true, z : false, c : true, v : false, q : false});
+    assert_eq!(cpu.get_spsr(), None);
+    assert_eq!((cpu.registers[1], cpu.registers[2]), (0, 2));
+}
+
+/* Run movs r0, #0xFF with the given rotate field
+return  <-  (r0, carry) */
+fn rotated_immediate(rotate : u32, carry_in : bool) -> (u32, bool) {
+    let mut cpu = load_words(&[0xE3B0_00FF | rotate << 8]);
+    cpu.set_flags(false, false, carry_in, false);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    (cpu.registers[0], cpu.cpsr.c)
+}
+
+#[test]
+fn immediate_rotate_fields_give_the_arm_values() {
+    /* no rotation keeps the carry flag, otherwise it is bit 31 of the result */
+    assert_eq!(rotated_immediate(0, true), (0xFF, true));
+    assert_eq!(rotated_immediate(0, false), (0xFF, false));
+    /* rotate right by 2 */
+    assert_eq!(rotated_immediate(1, false), (0xC000_003F, true));
+    /* rotate right by 30 */
+    assert_eq!(rotated_immediate(15, true), (0x3FC, false));
+}
+
+#[test]
+fn shifted_tst_carry_feeds_a_conditional() {
+    /* the lsl #1 shifts bit 31 out into C, the and itself is zero */
+    let cpu = run("mov r0, #0xFF\nmov r1, #0x80000000\ntst r0, r1, lsl #1\nmovcs r2, #1\nmovcc r2, #2\nmoveq r3, #3\nhalt");
+    assert_eq!((cpu.registers[2], cpu.registers[3]), (1, 3));
+
+    let cpu = run_with_carry("mov r1, #2\ntst r1, r1, lsr #1\nmovcs r2, #1\nmovcc r2, #2\nhalt");
+    assert_eq!(cpu.registers[2], 2);
+}
+
+#[test]
+fn teq_carry_is_bit_31_of_a_rotated_immediate() {
+    let cpu = run("teq r0, #0x80000000\nmovcs r2, #1\nhalt");
+    assert_eq!(cpu.registers[2], 1);
+    let cpu = run_with_carry("teq r0, #0x100\nmovcc r2, #1\nhalt");
+    assert_eq!(cpu.registers[2], 1);
+    /* an unrotated immediate leaves C */
+    let cpu = run_with_carry("teq r0, #0xFF\nmovcs r2, #1\nhalt");
+    assert_eq!(cpu.registers[2], 1);
+}
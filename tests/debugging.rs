@@ -0,0 +1,207 @@
+mod common;
+
+use emulate::{CPU, RuntimeError, StepResult, WatchKind};
+use common::{load, load_words, run, SharedBuffer};
+
+#[test]
+fn step_executes_one_instruction_at_a_time() {
+    let mut cpu = load("mov r0, #1\nmov r1, #2\nb end\nmov r2, #3\nend: halt");
+    assert_eq!(cpu.step(), Ok(StepResult::Continued));
+    assert_eq!((cpu.registers[0], cpu.registers[1]), (1, 0));
+    assert_eq!(cpu.step(), Ok(StepResult::Continued));
+    assert_eq!(cpu.registers[1], 2);
+    assert_eq!(cpu.step(), Ok(StepResult::Branched));
+    assert_eq!(cpu.step(), Ok(StepResult::Halted));
+    assert_eq!(cpu.registers[2], 0);
+}
+
+#[test]
+fn breakpoint_stops_before_the_instruction() {
+    let mut cpu = load("mov r0, #1\nmov r1, #2\nmov r2, #3\nhalt");
+    cpu.add_breakpoint(0x8);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Breakpoint(0x8)));
+    assert_eq!((cpu.registers[0], cpu.registers[1], cpu.registers[2]), (1, 2, 0));
+    assert!(cpu.remove_breakpoint(0x8));
+    assert!(!cpu.remove_breakpoint(0x8));
+    assert_eq!(cpu.resume(), Ok(StepResult::Halted));
+    assert_eq!(cpu.registers[2], 3);
+}
+
+#[test]
+fn trace_logs_instruction_and_changed_register() {
+    let mut cpu = load("mov r1, #2\nadd r0, r1, #4\nhalt");
+    let trace = SharedBuffer::default();
+    cpu.set_trace(true);
+    cpu.set_trace_output(Box::new(trace.clone()));
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    let trace = trace.contents();
+    let lines : Vec<&str> = trace.lines().collect();
+    assert!(lines.contains(&"0x00000004: 0xe2810004  add r0, r1, #4"), "{}", trace);
+    assert!(lines.contains(&"            r0 = 0x00000006"), "{}", trace);
+}
+
+#[test]
+fn zero_word_runs_as_andeq_when_halt_on_zero_is_disabled() {
+    let mut cpu = load_words(&[0xE3A0_0001, 0x0000_0000, 0xE3A0_1002]);
+    cpu.set_halt_on_zero(false);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[0], cpu.registers[1]), (1, 2));
+}
+
+#[test]
+fn zero_word_halts_by_default() {
+    let mut cpu = load_words(&[0xE3A0_0001, 0x0000_0000, 0xE3A0_1002]);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[0], cpu.registers[1]), (1, 0));
+}
+
+#[test]
+fn infinite_loop_stops_at_the_instruction_limit() {
+    let mut cpu = load("loop: b loop");
+    cpu.set_instruction_limit(1000);
+    assert_eq!(cpu.run_program(), Err(RuntimeError::InstructionLimitExceeded {limit : 1000}));
+    assert_eq!(cpu.instruction_count(), 1000);
+}
+
+#[test]
+fn reset_returns_to_the_new_machine_state() {
+    let mut cpu = load("mov r0, #0x100\nmov r1, #5\nstr r1, [r0]\ncmp r1, #6\nhalt");
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    cpu.registers[7] = 9;
+    let capacity = cpu.memory.capacity();
+    cpu.reset();
+    let baseline = CPU::new();
+    assert!(cpu.diff(&baseline).is_empty(), "{}", cpu.diff(&baseline));
+    assert_eq!(cpu.cpsr, baseline.cpsr);
+    assert_eq!((cpu.cycle_count(), cpu.instruction_count()), (0, 0));
+    assert!(cpu.coverage().is_empty());
+    assert_eq!(cpu.memory.capacity(), capacity);
+}
+
+#[test]
+fn write_watchpoint_stops_after_the_offending_store() {
+    let mut cpu = load("mov r0, #0x100\nmov r1, #1\nldr r2, [r0]\nstr r1, [r0]\nmov r3, #3\nhalt");
+    cpu.add_watchpoint(0x100, WatchKind::Write);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Watchpoint {addr : 0x100, kind : WatchKind::Write}));
+    /* the store at 0xc has completed but the next instruction has not run */
+    assert_eq!(cpu.coverage().last(), Some(&0xC));
+    assert_eq!((cpu.memory[0x100], cpu.registers[3]), (1, 0));
+    assert_eq!(cpu.resume(), Ok(StepResult::Halted));
+    assert_eq!(cpu.registers[3], 3);
+}
+
+#[test]
+fn read_watchpoint_ignores_stores() {
+    let mut cpu = load("mov r0, #0x100\nstr r0, [r0]\nldr r2, [r0]\nhalt");
+    cpu.add_watchpoint(0x100, WatchKind::Read);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Watchpoint {addr : 0x100, kind : WatchKind::Read}));
+    assert_eq!(cpu.coverage().last(), Some(&0x8));
+}
+
+#[test]
+fn preloaded_registers_and_flags_feed_a_single_step() {
+    /* addcs r0, r1, r2 */
+    let mut cpu = load_words(&[0x2081_0002]);
+    let mut regs = [0; 16];
+    regs[1] = 40;
+    regs[2] = 2;
+    cpu.set_registers(regs);
+    cpu.set_flags(false, false, true, false);
+    assert_eq!(cpu.step(), Ok(StepResult::Continued));
+    assert_eq!(cpu.registers[0], 42);
+    /* the same instruction is skipped with carry clear */
+    let mut cpu = load_words(&[0x2081_0002]);
+    cpu.set_registers(regs);
+    cpu.set_flags(true, true, false, true);
+    assert_eq!(cpu.step(), Ok(StepResult::Continued));
+    assert_eq!(cpu.registers[0], 0);
+    assert!(cpu.cpsr.n && cpu.cpsr.z && cpu.cpsr.v);
+}
+
+#[test]
+fn write_log_records_stores_in_order() {
+    let mut cpu = load("mov r0, #0x100\nmov r1, #7\nstr r1, [r0]\nstrb r1, [r0, #5]\nldr r2, [r0]\nstr r0, [r0, #8]\nhalt");
+    cpu.set_record_writes(true);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    /* entries hold the count of instructions executed including the store */
+    assert_eq!(cpu.take_write_log(), vec![(3, 0x100, 7), (4, 0x105, 7), (6, 0x108, 0x100)]);
+    assert!(cpu.take_write_log().is_empty());
+}
+
+#[test]
+fn write_log_is_empty_unless_enabled() {
+    let mut cpu = load("mov r0, #0x100\nstr r0, [r0]\nhalt");
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert!(cpu.take_write_log().is_empty());
+}
+
+#[test]
+fn restore_returns_to_the_checkpoint() {
+    let mut cpu = load("mov r0, #1\nadds r1, r0, #2\nstr r1, [r0, #0xFF]\nmov r2, #3\nsub r0, r0, #1\nhalt");
+    assert_eq!(cpu.step(), Ok(StepResult::Continued));
+    let snap = cpu.checkpoint();
+    for _ in 0..4 {assert_eq!(cpu.step(), Ok(StepResult::Continued))}
+    assert_ne!(cpu.checkpoint(), snap);
+    cpu.restore(&snap);
+    assert_eq!(cpu.checkpoint(), snap);
+    assert_eq!((cpu.registers[1], cpu.memory[0x100]), (0, 0));
+    /* execution resumes from the restored pipeline */
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[0], cpu.registers[1], cpu.registers[2], cpu.memory[0x100]), (0, 3, 3, 3));
+}
+
+#[test]
+fn coverage_omits_a_block_skipped_by_a_branch() {
+    /* the beq is never taken, so the block at skip never runs */
+    let cpu = run("mov r0, #1\ncmp r0, #0\nbeq skip\nmov r1, #1\nb end\nskip: mov r1, #2\nmov r2, #2\nend: halt");
+    assert_eq!(cpu.coverage(), vec![0x0, 0x4, 0x8, 0xC, 0x10, 0x1C]);
+    assert_eq!((cpu.registers[1], cpu.registers[2]), (1, 0));
+}
+
+#[test]
+fn halt_instruction_stops_before_the_following_code() {
+    let mut cpu = load("mov r0, #1\nhalt\nmov r1, #2");
+    cpu.set_halt_on_zero(false);
+    assert_eq!(cpu.step(), Ok(StepResult::Continued));
+    assert_eq!(cpu.step(), Ok(StepResult::Halted));
+    /* a halted machine stays halted */
+    assert_eq!(cpu.step(), Ok(StepResult::Halted));
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[0], cpu.registers[1]), (1, 0));
+    assert_eq!(cpu.coverage(), vec![0x0, 0x4]);
+}
+
+#[test]
+fn halt_constant_ends_raw_programs() {
+    let mut cpu = CPU::new();
+    cpu.poke_words(&[(0x0, 0xE3A0_0001), (0x4, emulate::HALT), (0x8, 0xE3A0_1002)]).unwrap();
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[0], cpu.registers[1]), (1, 0));
+}
+
+#[test]
+fn diff_after_an_add_shows_the_destination_and_flags() {
+    let source = "adds r0, r1, r2\nhalt";
+    let (mut before, mut after) = (load(source), load(source));
+    for cpu in [&mut before, &mut after].iter_mut() {
+        cpu.registers[1] = 0xFFFF_FFFF;
+        cpu.registers[2] = 1;
+        cpu.registers[0] = 7;
+    }
+    assert_eq!(after.step(), Ok(StepResult::Continued));
+    let diff = before.diff(&after);
+    /* the PC always moves on, the add sets Z and C */
+    assert_eq!(diff.registers, vec![(0, 7, 0), (15, before.registers[15], after.registers[15])]);
+    assert_eq!(diff.cpsr, Some((0, 0x6000_0000)));
+    assert!(diff.memory.is_empty());
+}
+
+#[test]
+fn faulting_watched_store_is_not_reported_later() {
+    let mut cpu = load("str r1, [r0]\nmov r3, #3\nhalt");
+    cpu.registers[0] = 0x10_0000;
+    cpu.add_watchpoint(0x10_0000, WatchKind::Write);
+    assert_eq!(cpu.step(), Err(RuntimeError::OutOfBounds {addr : 0x10_0000}));
+    assert_eq!(cpu.step(), Ok(StepResult::Continued));
+    assert_eq!(cpu.registers[3], 3);
+}
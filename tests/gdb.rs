@@ -0,0 +1,45 @@
+#![cfg(feature = "gdb")]
+
+mod common;
+
+use emulate::gdb::handle_connection;
+use common::load;
+
+/* Send packets to a machine running the given program, returning everything the stub replied */
+fn session(source : &str, packets : &[&str]) -> String {
+    let mut cpu = load(source);
+    let request : String = packets.iter()
+        .map(|packet| format!("${}#{:02x}", packet, packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))))
+        .collect();
+    let mut reply = Vec::new();
+    handle_connection(&mut cpu, &mut request.as_bytes(), &mut reply).unwrap();
+    String::from_utf8(reply).unwrap()
+}
+
+/* Split the stub's output into the acknowledged packet contents */
+fn replies(output : &str) -> Vec<&str> {
+    output.split('$').skip(1).map(|packet| packet.split('#').next().unwrap()).collect()
+}
+
+#[test]
+fn register_packet_lists_every_register() {
+    let output = session("mov r0, #0x12\nhalt", &["s", "g"]);
+    assert!(output.starts_with('+'));
+    let registers = replies(&output)[1];
+    /* 16 registers, 8 FPA registers and fps, then the cpsr, as 2 hex digits per byte */
+    assert_eq!(registers.len(), (16 + 25 + 1) * 8);
+    assert_eq!(&registers[..8], "12000000");
+    /* the pc is the address of the next instruction */
+    assert_eq!(&registers[15 * 8..16 * 8], "04000000");
+}
+
+#[test]
+fn breakpoints_stop_continue() {
+    let output = session("mov r0, #1\nmov r1, #2\nmov r2, #3\nhalt", &["Z0,8,4", "c", "m8,4", "z0,8,4", "c"]);
+    assert_eq!(replies(&output), vec!["OK", "S05", "0320a0e3", "OK", "W00"]);
+}
+
+#[test]
+fn unsupported_packets_get_an_empty_reply() {
+    assert_eq!(replies(&session("halt", &["qSupported", "?"])), vec!["", "S05"]);
+}
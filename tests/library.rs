@@ -0,0 +1,35 @@
+mod common;
+
+use std::convert::TryFrom;
+use emulate::{CPU, Cpsr, Register, StepResult};
+use common::load;
+
+#[test]
+fn registers_are_accessible_from_outside_the_crate() {
+    let mut cpu = CPU::new();
+    let r3 = Register::try_from(3).unwrap();
+    cpu.set_register(r3, 0xdead_beef);
+    assert_eq!(cpu.get_register(r3), 0xdead_beef);
+    assert!(Register::try_from(16).is_err());
+}
+
+#[test]
+fn programs_run_through_the_library() {
+    let mut cpu = load("mov r0, #7\nhalt");
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(cpu.get_register(Register::try_from(0).unwrap()), 7);
+    assert_eq!(cpu.cpsr, Cpsr {n : false, z : false, c : false, v : false, q : false});
+}
+
+#[test]
+fn packed_cpsr_round_trips() {
+    let mut cpu = CPU::new();
+    cpu.set_cpsr(0xA000_0000);
+    assert_eq!(cpu.get_cpsr(), 0xA000_0000);
+    assert_eq!((cpu.cpsr.n, cpu.cpsr.z, cpu.cpsr.c, cpu.cpsr.v), (true, false, true, false));
+    cpu.set_cpsr(0x5000_0000);
+    assert_eq!((cpu.cpsr.n, cpu.cpsr.z, cpu.cpsr.c, cpu.cpsr.v), (false, true, false, true));
+    /* bits that are not modelled are dropped */
+    cpu.set_cpsr(0xF000_00D3);
+    assert_eq!(cpu.get_cpsr(), 0xF000_0000);
+}
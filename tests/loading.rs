@@ -0,0 +1,201 @@
+use std::io::Cursor;
+
+use emulate::{CPU, LoadError, RuntimeError, StepResult, MEMSIZE};
+
+#[test]
+fn missing_file_is_an_io_error() {
+    let mut cpu = CPU::new();
+    assert!(matches!(cpu.load_program("does/not/exist.bin"), Err(LoadError::Io(_))));
+}
+
+#[test]
+fn oversized_binary_is_rejected() {
+    let mut cpu = CPU::with_memory(16);
+    let binary = [0u8; 20];
+    match cpu.load_program_from_reader(&mut &binary[..]) {
+        Err(LoadError::TooLarge {size, capacity}) => assert_eq!((size, capacity), (20, 16)),
+        other => panic!("expected TooLarge, got {:?}", other)
+    }
+}
+
+#[test]
+fn small_machine_bounds_accesses_at_its_size() {
+    let mut cpu = CPU::with_memory(0x400);
+    assert_eq!(cpu.memory.len(), 0x400);
+    assert!(cpu.poke_words(&[(0x3FC, 1)]).is_ok());
+    assert_eq!(cpu.poke_words(&[(0x400, 1)]), Err(RuntimeError::OutOfBounds {addr : 0x400}));
+    /* mov r1, #0x400; ldr r0, [r1, #-4]; ldr r0, [r1] */
+    cpu.poke_words(&[(0x0, 0xE3A0_1B01), (0x4, 0xE511_0004), (0x8, 0xE591_0000)]).unwrap();
+    assert_eq!(cpu.run_program(), Err(RuntimeError::OutOfBounds {addr : 0x400}));
+    assert_eq!(cpu.registers[0], 1);
+}
+
+#[test]
+fn binary_filling_a_small_machine_loads() {
+    let mut cpu = CPU::with_memory(0x400);
+    assert!(cpu.load_program_from_reader(&mut &[0u8; 0x400][..]).is_ok());
+    assert!(matches!(cpu.load_program_from_reader(&mut &[0u8; 0x401][..]), Err(LoadError::TooLarge {..})));
+}
+
+#[test]
+fn memory_dump_reloads_into_a_fresh_machine() {
+    let mut cpu = CPU::with_memory(0x100);
+    cpu.poke_words(&[(0x10, 0x1234_5678), (0xFC, 0xDEAD_BEEF)]).unwrap();
+    assert_eq!(cpu.dump_nonzero(), vec![(0x10, 0x1234_5678), (0xFC, 0xDEAD_BEEF)]);
+
+    let path = std::env::temp_dir().join(format!("emulate-dump-{}.bin", std::process::id()));
+    let path = path.to_str().unwrap();
+    cpu.dump_memory(path).unwrap();
+    let mut reloaded = CPU::with_memory(0x100);
+    let loaded = reloaded.load_program(path);
+    std::fs::remove_file(path).unwrap();
+    loaded.unwrap();
+    assert_eq!(reloaded.memory, cpu.memory);
+    assert_eq!(reloaded.dump_nonzero(), cpu.dump_nonzero());
+}
+
+#[test]
+fn program_loads_from_any_reader() {
+    let binary = emulate::asm::assemble("mov r0, #3\nadd r0, r0, #4\nhalt").unwrap();
+    let mut cpu = CPU::new();
+    cpu.load_program_from_reader(&mut Cursor::new(binary)).unwrap();
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(cpu.registers[0], 7);
+}
+
+#[test]
+fn binaries_up_to_memsize_are_accepted() {
+    for (size, fits) in [(MEMSIZE - 1, true), (MEMSIZE, true), (MEMSIZE + 1, false)] {
+        let mut cpu = CPU::new();
+        let binary = vec![0xAAu8; size];
+        assert_eq!(cpu.load_program_from_reader(&mut &binary[..]).is_ok(), fits, "binary of {} bytes", size);
+    }
+}
+
+/* Build a 32 bit little endian ARM ELF file with a single PT_LOAD segment
+entry    <-  entry point
+addr     <-  virtual address of the segment
+data     <-  the segment contents in the file
+memsize  <-  size of the segment in memory */
+fn elf(entry : u32, addr : u32, data : &[u8], memsize : u32) -> Vec<u8> {
+    let mut file = vec![0u8; 52 + 32];
+    file[..6].copy_from_slice(&[0x7F, b'E', b'L', b'F', 1, 1]);
+    file[0x12..0x14].copy_from_slice(&40u16.to_le_bytes());
+    file[0x18..0x1C].copy_from_slice(&entry.to_le_bytes());
+    file[0x1C..0x20].copy_from_slice(&52u32.to_le_bytes());
+    file[0x2A..0x2C].copy_from_slice(&32u16.to_le_bytes());
+    file[0x2C..0x2E].copy_from_slice(&1u16.to_le_bytes());
+    /* program header: type, offset, vaddr, paddr, filesz, memsz */
+    for (ind, field) in [1, 84, addr, addr, data.len() as u32, memsize].iter().enumerate() {
+        file[52 + ind * 4..56 + ind * 4].copy_from_slice(&field.to_le_bytes());
+    }
+    file.extend_from_slice(data);
+    file
+}
+
+#[test]
+fn elf_segment_is_loaded_and_run_from_its_entry() {
+    let code = emulate::asm::assemble("mov r0, #0\nmov r0, #5\nhalt").unwrap();
+    let mut cpu = CPU::with_memory(0x400);
+    /* the bss tail of the segment is zeroed */
+    cpu.poke_words(&[(0x20C, 0xFFFF_FFFF)]).unwrap();
+    cpu.load_program_from_reader(&mut &elf(0x204, 0x200, &code, 16)[..]).unwrap();
+    assert_eq!(cpu.memory[0x200..0x20C], code[..]);
+    assert_eq!(cpu.memory[0x20C..0x210], [0; 4]);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    /* the first instruction is before the entry point and is not executed */
+    assert_eq!(cpu.registers[0], 5);
+    assert_eq!(cpu.coverage(), vec![0x204, 0x208]);
+}
+
+#[test]
+fn elf_segment_outside_memory_is_rejected() {
+    let mut cpu = CPU::with_memory(0x400);
+    let result = cpu.load_program_from_reader(&mut &elf(0, 0x3FC, &[0; 8], 8)[..]);
+    assert!(matches!(result, Err(LoadError::SegmentOutOfRange {addr : 0x3FC, size : 8})));
+}
+
+/* Format an Intel HEX record with its checksum */
+fn hex_record(addr : u16, kind : u8, data : &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8, (addr >> 8) as u8, addr as u8, kind];
+    bytes.extend_from_slice(data);
+    let checksum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)).wrapping_neg();
+    bytes.push(checksum);
+    format!(":{}\n", bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<String>())
+}
+
+#[test]
+fn intel_hex_records_are_placed_until_end_of_file() {
+    let text = [
+        hex_record(0x10, 0, &[0x11, 0x22, 0x33, 0x44]),
+        /* extended linear address 0x00010000 */
+        hex_record(0, 4, &[0x00, 0x01]),
+        hex_record(0x20, 0, &[0xAA, 0xBB]),
+        hex_record(0, 1, &[]),
+        hex_record(0x30, 0, &[0xCC])
+    ].concat();
+    let mut cpu = CPU::with_memory(0x20000);
+    cpu.load_program_from_reader(&mut text.as_bytes()).unwrap();
+    assert_eq!(cpu.memory[0x10..0x14], [0x11, 0x22, 0x33, 0x44]);
+    assert_eq!(cpu.memory[0x10020..0x10022], [0xAA, 0xBB]);
+    assert_eq!(cpu.memory[0x10030], 0);
+    assert_eq!(cpu.dump_nonzero().len(), 2);
+}
+
+#[test]
+fn intel_hex_with_a_bad_checksum_is_rejected() {
+    let mut record = hex_record(0x10, 0, &[0x11]);
+    record.replace_range(record.len() - 3.., "00\n");
+    let mut cpu = CPU::new();
+    assert!(matches!(cpu.load_ihex(&record), Err(LoadError::InvalidHex {line : 1, ..})));
+}
+
+#[test]
+fn poisoned_memory_shows_the_pattern_around_the_loaded_program() {
+    /* no halt, so the machine stops on the first poison word after the program */
+    let binary = emulate::asm::assemble("mov r1, #0x100\nldr r0, [r1]").unwrap();
+    let mut cpu = CPU::with_poison(0xDEAD_BEEF);
+    cpu.load_program_from_reader(&mut &binary[..]).unwrap();
+    assert_eq!(cpu.memory[..8], binary[..]);
+    assert_eq!(cpu.memory[8..12], 0xDEAD_BEEFu32.to_le_bytes());
+    assert_eq!(cpu.memory[MEMSIZE - 4..], 0xDEAD_BEEFu32.to_le_bytes());
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(cpu.registers[0], 0xDEAD_BEEF);
+    assert_eq!(cpu.coverage(), vec![0x0, 0x4]);
+
+    /* a reset poisons memory again */
+    cpu.reset();
+    assert_eq!(cpu.memory[..4], 0xDEAD_BEEFu32.to_le_bytes());
+}
+
+#[test]
+fn segments_land_at_their_addresses() {
+    let code = emulate::asm::assemble("mov r1, #0x100\nldr r0, [r1, #4]\nhalt").unwrap();
+    let mut cpu = CPU::with_memory(0x200);
+    cpu.load_segment(&code, 0).unwrap();
+    cpu.load_segment(&[1, 2, 3, 4, 5, 6, 7, 8], 0x100).unwrap();
+    assert_eq!(cpu.memory[..12], code[..]);
+    /* the bytes between and after the segments are untouched */
+    assert!(cpu.memory[12..0x100].iter().chain(&cpu.memory[0x108..]).all(|byte| *byte == 0));
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(cpu.registers[0], 0x0807_0605);
+}
+
+#[test]
+fn segments_outside_memory_are_rejected() {
+    let mut cpu = CPU::with_memory(0x10);
+    assert!(matches!(cpu.load_segment(&[1; 8], 0xC), Err(LoadError::SegmentOutOfRange {addr : 0xC, size : 8})));
+    assert!(matches!(cpu.load_segment(&[1], usize::MAX), Err(LoadError::SegmentOutOfRange {..})));
+    assert!(cpu.memory.iter().all(|byte| *byte == 0));
+}
+
+#[test]
+fn code_length_stops_before_trailing_data() {
+    /* the word after the add would execute as mov r1, #2 */
+    let mut cpu = CPU::new();
+    cpu.poke_words(&[(0x0, 0xE3A0_0001), (0x4, 0xE280_0001), (0x8, 0xE3A0_1002)]).unwrap();
+    cpu.set_code_length(8);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[0], cpu.registers[1]), (2, 0));
+    assert_eq!(cpu.coverage(), vec![0x0, 0x4]);
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use std::{io, sync::Mutex};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use emulate::StepResult;
+use common::load;
+
+/* Logger keeping every record, installed once for this test binary */
+struct CapturingLogger(Mutex<Vec<(Level, String)>>);
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _ : &Metadata) -> bool {true}
+    fn log(&self, record : &Record) {self.0.lock().unwrap().push((record.level(), record.args().to_string()))}
+    fn flush(&self) {}
+}
+
+static LOGGER : CapturingLogger = CapturingLogger(Mutex::new(Vec::new()));
+
+#[test]
+fn gpio_accesses_emit_info_records() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Info);
+
+    let mut cpu = load("ldr r0, =0x20200000\nldr r1, [r0, #4]\nmov r2, #1\nstr r2, [r0, #0x1C]\nstr r2, [r0, #0x28]\nhalt");
+    cpu.set_trace(true);
+    cpu.set_trace_output(Box::new(io::sink()));
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(*LOGGER.0.lock().unwrap(), vec![
+        (Level::Info, String::from("One GPIO pin from 10 to 19 has been accessed")),
+        (Level::Info, String::from("PIN ON")),
+        (Level::Info, String::from("PIN OFF"))
+    ]);
+}
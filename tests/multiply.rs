@@ -0,0 +1,77 @@
+mod common;
+
+use emulate::StepResult;
+use common::{load_words, run};
+
+/* mvn r2, #0; mov r3, #0x80000000 */
+const SETUP : [u32; 2] = [0xE3E0_2000, 0xE3A0_3102];
+
+/* Run a long multiply of 0xffffffff by 0x80000000 into r1:r0 with r1:r0 initially set to hi:lo */
+fn long_multiply(instruction : u32, hi : u32, lo : u32) -> (u32, u32) {
+    let mut cpu = load_words(&[SETUP[0], SETUP[1], instruction]);
+    cpu.registers[0] = lo;
+    cpu.registers[1] = hi;
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    (cpu.registers[1], cpu.registers[0])
+}
+
+#[test]
+fn umull_produces_both_halves() {
+    /* umull r0, r1, r2, r3 */
+    assert_eq!(long_multiply(0xE081_0392, 5, 5), (0x7FFF_FFFF, 0x8000_0000));
+}
+
+#[test]
+fn smull_sign_extends_operands() {
+    /* smull r0, r1, r2, r3: -1 * -0x80000000 */
+    assert_eq!(long_multiply(0xE0C1_0392, 5, 5), (0, 0x8000_0000));
+}
+
+#[test]
+fn umlal_accumulates_into_both_halves() {
+    /* umlal r0, r1, r2, r3, with a carry out of the low half */
+    assert_eq!(long_multiply(0xE0A1_0392, 2, 0x8000_0001), (0x8000_0002, 1));
+}
+
+#[test]
+fn smlal_accumulates_signed() {
+    /* smlal r0, r1, r2, r3: 0x80000000 + -1 */
+    assert_eq!(long_multiply(0xE0E1_0392, 0xFFFF_FFFF, 0xFFFF_FFFF), (0, 0x7FFF_FFFF));
+}
+
+#[test]
+fn short_multiply_truncates_without_overflow() {
+    let cpu = run("mvn r2, #0\nmul r4, r2, r2\nmov r0, #0x10000\nmla r5, r0, r0, r2\nhalt");
+    assert_eq!((cpu.registers[4], cpu.registers[5]), (1, 0xFFFF_FFFF));
+}
+
+#[test]
+fn muls_sets_z_for_a_zero_result() {
+    /* 0x10000 * 0x10000 truncates to 0, C and V are left unchanged */
+    let mut cpu = common::load("mov r0, #0x10000\nmuls r1, r0, r0\nhalt");
+    cpu.set_flags(true, false, true, true);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(cpu.registers[1], 0);
+    assert!(cpu.cpsr.z && !cpu.cpsr.n && cpu.cpsr.c && cpu.cpsr.v);
+}
+
+#[test]
+fn muls_sets_n_for_a_negative_result() {
+    let cpu = run("mvn r0, #0\nmov r1, #3\nmuls r2, r0, r1\nhalt");
+    assert_eq!(cpu.registers[2], 0xFFFF_FFFD);
+    assert!(cpu.cpsr.n && !cpu.cpsr.z);
+}
+
+#[test]
+fn long_multiply_flags_use_the_whole_result() {
+    /* umulls r0, r1, r2, r3 of 0x80000000 * 2, a zero low word but a non-zero high word */
+    let mut cpu = load_words(&[0xE3A0_2102, 0xE3A0_3002, 0xE091_0392]);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[1], cpu.registers[0]), (1, 0));
+    assert!(!cpu.cpsr.z && !cpu.cpsr.n);
+    /* smulls r0, r1, r2, r3 of -1 * 3 */
+    let mut cpu = load_words(&[0xE3E0_2000, 0xE3A0_3003, 0xE0D1_0392]);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[1], cpu.registers[0]), (0xFFFF_FFFF, 0xFFFF_FFFD));
+    assert!(cpu.cpsr.n && !cpu.cpsr.z);
+}
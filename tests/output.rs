@@ -0,0 +1,75 @@
+mod common;
+
+use emulate::{RuntimeError, StepResult};
+use common::{load, run, SharedBuffer};
+
+#[test]
+fn state_is_printed_one_register_per_line() {
+    let mut cpu = run("mov r0, #5\nmvn r1, #0\ncmp r0, #5\nhalt");
+    cpu.registers[13] = 0x100;
+    let mut out = Vec::new();
+    cpu.write_state(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines : Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "Registers:");
+    assert_eq!(lines[1], "$0  :          5 (0x00000005)");
+    assert_eq!(lines[2], "$1  :         -1 (0xffffffff)");
+    assert_eq!(lines[14], "SP  :        256 (0x00000100)");
+    assert_eq!(lines[15], "LR  :          0 (0x00000000)");
+    assert!(lines[16].starts_with("PC  : "));
+    /* Z and C set by the comparison are bits 30 and 29 */
+    assert_eq!(lines[17], "CPSR: 1610612736 (0x60000000)");
+    assert_eq!(lines[19], "Non-zero memory:");
+    /* the first instruction, mov r0, #5, in memory byte order */
+    assert_eq!(lines[20], "0x00000000: 0x0500a0e3");
+}
+
+#[test]
+fn swi_0_writes_a_character() {
+    let mut cpu = load("mov r0, #0x48\nswi 0\nmov r0, #0x69\nswi 0\nhalt");
+    let output = SharedBuffer::default();
+    cpu.set_output(Box::new(output.clone()));
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(output.contents(), "Hi");
+}
+
+#[test]
+fn unknown_swi_is_an_error() {
+    let mut cpu = load("swi 7");
+    assert!(matches!(cpu.run_program(), Err(RuntimeError::InvalidInstruction {..})));
+}
+
+#[test]
+fn state_json_lists_registers_flags_and_memory() {
+    let mut cpu = emulate::CPU::with_memory(0x10);
+    cpu.registers[0] = 7;
+    cpu.registers[15] = 0xFFFF_FFFF;
+    cpu.set_flags(true, false, true, false);
+    cpu.poke_words(&[(0x8, 42)]).unwrap();
+    assert_eq!(cpu.state_json(), concat!(
+        "{\"registers\":[7,0,0,0,0,0,0,0,0,0,0,0,0,0,0,4294967295],",
+        "\"cpsr\":{\"n\":true,\"z\":false,\"c\":true,\"v\":false},",
+        "\"cycles\":0,\"memory\":[{\"address\":8,\"value\":42}]}"
+    ));
+}
+
+#[test]
+fn csv_state_has_one_value_per_line() {
+    let mut cpu = emulate::CPU::with_memory(0x10);
+    cpu.registers[1] = 0xFFFF_FFFE;
+    cpu.registers[13] = 0x10;
+    cpu.set_flags(false, true, true, false);
+    cpu.poke_words(&[(0x4, 0x1234_5678)]).unwrap();
+    let mut out = Vec::new();
+    cpu.write_state_csv(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines : Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 18);
+    assert_eq!(lines[0], "r0,0,0x00000000");
+    assert_eq!(lines[1], "r1,-2,0xfffffffe");
+    assert_eq!(lines[13], "sp,16,0x00000010");
+    assert_eq!(lines[14], "lr,0,0x00000000");
+    assert_eq!(lines[15], "pc,0,0x00000000");
+    assert_eq!(lines[16], "cpsr,1610612736,0x60000000");
+    assert_eq!(lines[17], "0x00000004,0x78563412");
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use std::{cell::RefCell, ops::Range, rc::Rc};
+
+use emulate::{MmioDevice, RuntimeError, StepResult, UartDevice};
+use common::{load, run, SharedBuffer};
+
+#[test]
+fn gpio_set_and_clear_registers_change_pin_levels() {
+    let cpu = run("ldr r0, =0x20200000\nmov r1, #0x10000\norr r1, r1, #8\nstr r1, [r0, #0x1C]\nmov r2, #8\nstr r2, [r0, #0x28]\nmov r3, #2\nstr r3, [r0, #0x20]\nhalt");
+    assert!(cpu.gpio_pin(16));
+    assert!(!cpu.gpio_pin(3));
+    /* pins 32-53 are in the second set register */
+    assert!(cpu.gpio_pin(33));
+    assert!(!cpu.gpio_pin(54));
+}
+
+#[test]
+fn gpio_function_select_reads_its_address() {
+    let cpu = run("ldr r0, =0x20200000\nldr r1, [r0, #4]\nhalt");
+    assert_eq!(cpu.registers[1], 0x2020_0004);
+}
+
+/* Device at 0x1000-0x1010 recording writes and reading back a constant */
+struct MockDevice(Rc<RefCell<Vec<(u32, u32)>>>);
+
+impl MmioDevice for MockDevice {
+    fn read(&mut self, addr : u32) -> u32 {addr | 0xAB00_0000}
+    fn write(&mut self, addr : u32, val : u32) {self.0.borrow_mut().push((addr, val))}
+    fn range(&self) -> Range<u32> {0x1000..0x1010}
+}
+
+#[test]
+fn registered_device_receives_transfers_in_its_range() {
+    let writes = Rc::new(RefCell::new(Vec::new()));
+    let mut cpu = load("mov r0, #0x1000\nmov r1, #7\nstr r1, [r0, #4]\nldr r2, [r0, #8]\nstr r1, [r0, #0x10]\nhalt");
+    cpu.add_device(Box::new(MockDevice(writes.clone())));
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(*writes.borrow(), vec![(0x1004, 7)]);
+    assert_eq!(cpu.registers[2], 0xAB00_1008);
+    /* stores outside the range go to RAM, and those inside do not */
+    assert_eq!(cpu.memory[0x1010], 7);
+    assert_eq!(cpu.memory[0x1004], 0);
+}
+
+#[test]
+fn uart_prints_a_string_from_a_loop() {
+    let mut cpu = load("ldr r0, =0x20201000\nmov r1, #0x100\nloop: ldrb r2, [r1], #1\ncmp r2, #0\nbeq done\n\
+        wait: ldr r3, [r0, #4]\ntst r3, #1\nbeq wait\nstr r2, [r0]\nb loop\ndone: halt");
+    /* "Hi\n" */
+    cpu.poke_words(&[(0x100, 0x000A_6948)]).unwrap();
+    let output = SharedBuffer::default();
+    cpu.add_device(Box::new(UartDevice::new(output.clone())));
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(output.contents(), "Hi\n");
+}
+
+#[test]
+fn stores_into_rom_fault_while_loads_succeed() {
+    let mut cpu = emulate::CPU::new();
+    cpu.set_rom_range(0, 0x100);
+    let binary = emulate::asm::assemble("mov r0, #0x80\nldr r1, [r0, #-0x80]\nstr r1, [r0, #0x80]\nstrb r1, [r0]\nhalt").unwrap();
+    cpu.load_program_from_reader(&mut &binary[..]).unwrap();
+    assert_eq!(cpu.run_program(), Err(RuntimeError::WriteToReadOnly {addr : 0x80}));
+    /* the load from ROM and the store above it completed */
+    assert_eq!(cpu.registers[1], 0xE3A0_0080);
+    assert_eq!(cpu.memory[0x100], 0x80);
+    assert_eq!(cpu.poke_words(&[(0xFC, 0)]), Err(RuntimeError::WriteToReadOnly {addr : 0xFC}));
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn cycles_follow_instruction_timing() {
+    /* mov (1) + str (2) + ldr (3) + cmp (1) + skipped movne (1) + b (3) + halt (3) */
+    let cpu = run("mov r0, #0x100\nstr r0, [r0]\nldr r1, [r0]\ncmp r0, r1\nmovne r2, #1\nb next\nnext: halt");
+    assert_eq!(cpu.cycle_count(), 1 + 2 + 3 + 1 + 1 + 3 + 3);
+}
+
+#[test]
+fn benchmark_counts_every_instruction_of_a_loop() {
+    /* mov, 10 iterations of subs and bne, then halt */
+    let mut cpu = common::load("mov r0, #10\nloop: subs r0, r0, #1\nbne loop\nhalt");
+    let stats = cpu.run_benchmark().unwrap();
+    assert_eq!(stats.instructions, 1 + 10 * 2 + 1);
+    assert_eq!(stats.instructions, cpu.instruction_count());
+    assert!(stats.instructions_per_second >= 0.0);
+}
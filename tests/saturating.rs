@@ -0,0 +1,40 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn qadd_clamps_at_the_maximum() {
+    let cpu = run("mvn r1, #0x80000000\nmov r2, #1\nqadd r0, r1, r2\nhalt");
+    assert_eq!(cpu.registers[0], 0x7FFF_FFFF);
+    assert!(cpu.cpsr.q);
+}
+
+#[test]
+fn qsub_clamps_at_the_minimum() {
+    let cpu = run("mov r1, #0x80000000\nmov r2, #1\nqsub r0, r1, r2\nhalt");
+    assert_eq!(cpu.registers[0], 0x8000_0000);
+    assert!(cpu.cpsr.q);
+}
+
+#[test]
+fn results_in_range_leave_q_clear() {
+    let cpu = run("mov r1, #5\nmvn r2, #2\nqadd r0, r1, r2\nqsub r3, r1, r2\nhalt");
+    assert_eq!((cpu.registers[0], cpu.registers[3]), (2, 8));
+    assert!(!cpu.cpsr.q);
+}
+
+#[test]
+fn doubling_saturates_before_the_add() {
+    /* qdadd r0, r1, r2: 0x40000000 doubled clamps to 0x7fffffff, then adding -1 stays in range */
+    let cpu = run("mvn r1, #0\nmov r2, #0x40000000\nqdadd r0, r1, r2\nhalt");
+    assert_eq!(cpu.registers[0], 0x7FFF_FFFE);
+    assert!(cpu.cpsr.q);
+}
+
+#[test]
+fn q_is_sticky_across_other_instructions() {
+    let cpu = run("mvn r1, #0x80000000\nqadd r0, r1, r1\nmovs r2, #0\nadds r3, r1, r1\nqadd r4, r2, r2\nhalt");
+    assert_eq!(cpu.registers[0], 0x7FFF_FFFF);
+    /* flag setting instructions change N, Z, C and V but not Q */
+    assert!(cpu.cpsr.q && cpu.cpsr.v);
+}
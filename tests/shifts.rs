@@ -0,0 +1,92 @@
+mod common;
+
+use emulate::StepResult;
+use common::{load, run};
+
+/* Shift r1 by r2 with movs r0, r1, <shift> r2, returning (result, carry) */
+fn shift_by_register(shift : &str, value : u32, amount : u32) -> (u32, bool) {
+    let mut cpu = load(&format!("movs r0, r1, {} r2\nhalt", shift));
+    cpu.registers[1] = value;
+    cpu.registers[2] = amount;
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    (cpu.registers[0], cpu.cpsr.c)
+}
+
+#[test]
+fn register_shift_of_32() {
+    assert_eq!(shift_by_register("lsl", 1, 32), (0, true));
+    assert_eq!(shift_by_register("lsr", 0x8000_0000, 32), (0, true));
+    assert_eq!(shift_by_register("asr", 0x8000_0000, 32), (0xFFFF_FFFF, true));
+    assert_eq!(shift_by_register("ror", 0x8000_0001, 32), (0x8000_0001, true));
+}
+
+#[test]
+fn register_shift_of_33() {
+    assert_eq!(shift_by_register("lsl", 0xFFFF_FFFF, 33), (0, false));
+    assert_eq!(shift_by_register("lsr", 0xFFFF_FFFF, 33), (0, false));
+    assert_eq!(shift_by_register("asr", 0x8000_0000, 33), (0xFFFF_FFFF, true));
+    assert_eq!(shift_by_register("ror", 0x0000_0003, 33), (0x8000_0001, true));
+}
+
+#[test]
+fn register_shift_of_255() {
+    assert_eq!(shift_by_register("lsl", 0xFFFF_FFFF, 255), (0, false));
+    assert_eq!(shift_by_register("lsr", 0xFFFF_FFFF, 255), (0, false));
+    assert_eq!(shift_by_register("asr", 0x4000_0000, 255), (0, false));
+}
+
+#[test]
+fn register_shift_uses_only_the_low_byte() {
+    /* 0x101 shifts by 1, 0x100 by 0 leaving the value and carry unchanged */
+    assert_eq!(shift_by_register("lsl", 3, 0x101), (6, false));
+    let cpu = run("mov r1, #5\nmov r2, #0x100\nmovs r0, r1, lsl r2\nhalt");
+    assert_eq!((cpu.registers[0], cpu.cpsr.c), (5, false));
+}
+
+/* Shift r1 by an immediate with movs r0, r1, <shift>, starting with carry set to carry_in */
+fn shift_by_immediate(shift : &str, value : u32, carry_in : bool) -> (u32, bool) {
+    let mut cpu = load(&format!("movs r0, r1, {}\nhalt", shift));
+    cpu.registers[1] = value;
+    cpu.set_flags(false, false, carry_in, false);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    (cpu.registers[0], cpu.cpsr.c)
+}
+
+#[test]
+fn immediate_shift_of_1() {
+    assert_eq!(shift_by_immediate("lsl #1", 0x8000_0001, false), (2, true));
+    assert_eq!(shift_by_immediate("lsr #1", 0x8000_0001, false), (0x4000_0000, true));
+    assert_eq!(shift_by_immediate("asr #1", 0x8000_0002, true), (0xC000_0001, false));
+    assert_eq!(shift_by_immediate("ror #1", 0x0000_0003, false), (0x8000_0001, true));
+}
+
+#[test]
+fn immediate_shift_of_31() {
+    assert_eq!(shift_by_immediate("lsl #31", 0x0000_0003, false), (0x8000_0000, true));
+    assert_eq!(shift_by_immediate("lsr #31", 0xC000_0000, false), (1, true));
+    assert_eq!(shift_by_immediate("asr #31", 0x8000_0000, false), (0xFFFF_FFFF, false));
+    assert_eq!(shift_by_immediate("ror #31", 0x8000_0001, false), (0x0000_0003, false));
+}
+
+#[test]
+fn ror_of_0_is_rrx() {
+    assert_eq!(shift_by_immediate("rrx", 0x0000_0002, false), (0x0000_0001, false));
+}
+
+#[test]
+fn rrx_rotates_through_carry() {
+    /* the old carry becomes bit 31 and bit 0 becomes the new carry */
+    assert_eq!(shift_by_immediate("rrx", 0x0000_0003, true), (0x8000_0001, true));
+    /* without the S bit the carry set by cmp is shifted in but not changed */
+    let cpu = run("mov r1, #1\ncmp r1, r1\nmov r0, r1, rrx\nhalt");
+    assert_eq!((cpu.registers[0], cpu.cpsr.c), (0x8000_0000, true));
+}
+
+#[test]
+fn ror_by_register_of_32_40_and_64() {
+    /* multiples of 32 leave the value unchanged with carry from bit 31, others rotate by the amount mod 32 */
+    assert_eq!(shift_by_register("ror", 0x8000_00F0, 32), (0x8000_00F0, true));
+    assert_eq!(shift_by_register("ror", 0x0000_00F0, 32), (0x0000_00F0, false));
+    assert_eq!(shift_by_register("ror", 0x0000_0180, 40), (0x8000_0001, true));
+    assert_eq!(shift_by_register("ror", 0x8000_00F0, 64), (0x8000_00F0, true));
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use emulate::{CPU, StepResult};
+use common::run;
+
+/* swi #1, the Thumb halt */
+const HALT : u16 = 0xDF01;
+
+/* Run Thumb halfwords from address 0 until they halt */
+fn thumb(halfwords : &[u16]) -> CPU {
+    let mut cpu = CPU::new();
+    for (ind, halfword) in halfwords.iter().chain(&[HALT]).enumerate() {
+        cpu.memory[ind * 2..ind * 2 + 2].copy_from_slice(&halfword.to_le_bytes());
+    }
+    cpu.set_thumb(true);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    cpu
+}
+
+/* Check the low registers and flags agree between a Thumb program and its ARM counterpart */
+fn assert_equivalent(halfwords : &[u16], source : &str) {
+    let (thumb, arm) = (thumb(halfwords), run(source));
+    assert_eq!(thumb.registers[..8], arm.registers[..8]);
+    assert_eq!(thumb.cpsr, arm.cpsr);
+}
+
+#[test]
+fn immediate_arithmetic_matches_arm() {
+    /* movs r0, #5; adds r0, #250; movs r1, #3; subs r1, #4 */
+    assert_equivalent(&[0x2005, 0x30FA, 0x2103, 0x3904], "movs r0, #5\nadds r0, r0, #250\nmovs r1, #3\nsubs r1, r1, #4\nswi 1\n");
+}
+
+#[test]
+fn shift_matches_arm() {
+    /* movs r0, #3; lsls r1, r0, #31 */
+    assert_equivalent(&[0x2003, 0x07C1], "movs r0, #3\nmovs r1, r0, lsl #31\nswi 1\n");
+}
+
+#[test]
+fn multiply_matches_arm() {
+    /* movs r0, #6; movs r1, #7; muls r0, r1 */
+    assert_equivalent(&[0x2006, 0x2107, 0x4348], "movs r0, #6\nmovs r1, #7\nmuls r2, r1, r0\nmov r0, r2\nmov r2, #0\nswi 1\n");
+}
+
+#[test]
+fn multiply_may_square_a_register() {
+    /* movs r0, #0x10; lsls r0, r0, #12; muls r0, r0 overflows to zero */
+    let cpu = thumb(&[0x2010, 0x0300, 0x4340]);
+    assert_eq!(cpu.registers[0], 0);
+    assert!(cpu.cpsr.z && !cpu.cpsr.n);
+
+    /* movs r3, #9; muls r3, r3 */
+    let cpu = thumb(&[0x2309, 0x435B]);
+    assert_eq!(cpu.registers[3], 81);
+    assert!(!cpu.cpsr.z);
+}
@@ -0,0 +1,245 @@
+mod common;
+
+use emulate::{Endian, RuntimeError, StepResult};
+use common::{load_words, run};
+
+/* mov r0, #1; mov r1, #2; mov r2, #3 */
+const SETUP : [u32; 3] = [0xE3A0_0001, 0xE3A0_1002, 0xE3A0_2003];
+
+#[test]
+fn stmfd_ldmfd_round_trip() {
+    /* stmfd sp!, {r0-r2}; ldmfd sp!, {r3-r5} */
+    let mut cpu = load_words(&[SETUP[0], SETUP[1], SETUP[2], 0xE92D_0007, 0xE8BD_0038]);
+    cpu.set_stack_pointer(0x100);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(cpu.registers[3..6], [1, 2, 3]);
+    assert_eq!(cpu.registers[13], 0x100);
+    /* the lowest register is stored at the lowest address */
+    assert_eq!(cpu.memory[0xF4..0x100], [1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0]);
+}
+
+#[test]
+fn stmfd_writeback_moves_stack_pointer() {
+    /* stmfd sp!, {r0-r2} */
+    let mut cpu = load_words(&[SETUP[0], SETUP[1], SETUP[2], 0xE92D_0007]);
+    cpu.set_stack_pointer(0x100);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(cpu.registers[13], 0xF4);
+}
+
+/* Load a word at 0x100 and r0 = 0x100, then run the transfer instructions given */
+fn run_on_word(word : u32, transfers : &[u32]) -> emulate::CPU {
+    /* mov r0, #0x100 */
+    let mut program = vec![0xE3A0_0C01];
+    program.extend_from_slice(transfers);
+    let mut cpu = load_words(&program);
+    cpu.poke_words(&[(0x100, word)]).unwrap();
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    cpu
+}
+
+#[test]
+fn byte_loads_zero_and_sign_extend() {
+    /* ldrb r1, [r0]; ldrsb r2, [r0]; ldrb r3, [r0, #2] */
+    let cpu = run_on_word(0x8081_F2F3, &[0xE5D0_1000, 0xE1D0_20D0, 0xE5D0_3002]);
+    assert_eq!(cpu.registers[1..4], [0xF3, 0xFFFF_FFF3, 0x81]);
+}
+
+#[test]
+fn halfword_loads_zero_and_sign_extend() {
+    /* ldrh r1, [r0]; ldrsh r2, [r0]; ldrh r3, [r0, #2]; ldrsh r4, [r0, #2] */
+    let cpu = run_on_word(0x8081_72F3, &[0xE1D0_10B0, 0xE1D0_20F0, 0xE1D0_30B2, 0xE1D0_40F2]);
+    assert_eq!(cpu.registers[1..5], [0x72F3, 0x72F3, 0x8081, 0xFFFF_8081]);
+}
+
+#[test]
+fn byte_and_halfword_stores_write_only_their_size() {
+    /* mov r1, #0xAB; strb r1, [r0, #1]; orr r1, r1, #0xCD00; strh r1, [r0, #2] */
+    let cpu = run_on_word(0x1111_1111, &[0xE3A0_10AB, 0xE5C0_1001, 0xE381_1CCD, 0xE1C0_10B2]);
+    assert_eq!(cpu.memory[0x100..0x104], [0x11, 0xAB, 0xAB, 0xCD]);
+}
+
+#[test]
+fn pre_indexed_writeback_updates_the_base() {
+    let cpu = common::run("mov r1, #0x100\nmov r2, #9\nstr r2, [r1, #4]!\nldr r0, [r1, #4]!\nhalt");
+    assert_eq!(cpu.registers[1], 0x108);
+    assert_eq!(cpu.memory[0x104], 9);
+    assert_eq!(cpu.registers[0], 0);
+}
+
+#[test]
+fn pre_indexed_without_writeback_keeps_the_base() {
+    let cpu = common::run("mov r1, #0x100\nmov r2, #9\nstr r2, [r1, #4]\nldr r0, [r1, #4]\nhalt");
+    assert_eq!((cpu.registers[0], cpu.registers[1]), (9, 0x100));
+}
+
+#[test]
+fn post_indexed_transfers_use_the_base_then_update_it() {
+    let cpu = common::run("mov r1, #0x100\nmov r2, #9\nstr r2, [r1], #4\nldr r0, [r1], #-4\nhalt");
+    assert_eq!(cpu.memory[0x100], 9);
+    assert_eq!((cpu.registers[0], cpu.registers[1]), (0, 0x100));
+}
+
+#[test]
+fn writeback_to_rd_is_rejected() {
+    let mut cpu = common::load("mov r1, #0x100\nldr r1, [r1, #4]!\nhalt");
+    assert!(matches!(cpu.run_program(), Err(RuntimeError::InvalidEncoding {reason : "Data Transfer instruction writes back to Rd", ..})));
+}
+
+#[test]
+fn strb_into_the_middle_of_a_word_changes_one_byte() {
+    /* mov r1, #0xAB; strb r1, [r0, #2]; ldr r2, [r0]; ldrb r3, [r0, #2] */
+    let cpu = run_on_word(0x1122_3344, &[0xE3A0_10AB, 0xE5C0_1002, 0xE590_2000, 0xE5D0_3002]);
+    assert_eq!(cpu.memory[0x100..0x104], [0x44, 0x33, 0xAB, 0x11]);
+    assert_eq!((cpu.registers[2], cpu.registers[3]), (0x11AB_3344, 0xAB));
+}
+
+#[test]
+fn strb_stores_only_the_low_byte() {
+    let cpu = common::run("mov r0, #0x100\nmvn r1, #0\nstr r1, [r0, #4]\nmov r1, #0x1200\norr r1, r1, #0x34\nstrb r1, [r0, #5]\nhalt");
+    assert_eq!(cpu.memory[0x104..0x108], [0xFF, 0x34, 0xFF, 0xFF]);
+}
+
+#[test]
+fn negative_offsets_subtract_from_the_base() {
+    let mut cpu = common::load("mov r1, #0x108\nmov r2, #4\nldr r0, [r1, -r2]\nldr r3, [r1, #-8]\nldr r4, [r1, -r2, lsl #1]\nhalt");
+    cpu.poke_words(&[(0x100, 0x11), (0x104, 0x22)]).unwrap();
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[0], cpu.registers[3], cpu.registers[4]), (0x22, 0x11, 0x11));
+}
+
+#[test]
+fn large_negative_register_offsets_wrap_consistently() {
+    /* r2 = 0xfffffffc, so subtracting it adds 4 */
+    let mut cpu = common::load("mov r1, #0x100\nmvn r2, #3\nldr r0, [r1, -r2]\nldr r3, [r1, r2]\nhalt");
+    cpu.poke_words(&[(0x104, 0x33), (0xFC, 0x44)]).unwrap();
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[0], cpu.registers[3]), (0x33, 0x44));
+}
+
+#[test]
+fn post_indexed_offset_register_equal_to_rd_faults() {
+    let mut cpu = common::load("mov r1, #0x100\nmov r2, #4\nldr r2, [r1], r2\nhalt");
+    assert!(matches!(cpu.run_program(), Err(RuntimeError::InvalidInstruction {reason : "Data Transfer instruction uses same register as Rn, Rm", ..})));
+}
+
+#[test]
+fn offset_register_value_equal_to_rd_number_does_not_fault() {
+    /* r0 holds 1, the register number of Rd, which is allowed */
+    let mut cpu = common::load("mov r3, #0x100\nmov r0, #1\nldr r1, [r3], r0\nhalt");
+    cpu.poke_words(&[(0x100, 0x55)]).unwrap();
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!((cpu.registers[1], cpu.registers[3]), (0x55, 0x101));
+}
+
+#[test]
+fn initialised_stack_pointer_supports_push_and_pop() {
+    /* mov r0, #1; mov r1, #2; stmfd sp!, {r0, r1}; mov r0, #0; mov r1, #0; ldmfd sp!, {r2, r3} */
+    let mut cpu = load_words(&[SETUP[0], SETUP[1], 0xE92D_0003, 0xE3A0_0000, 0xE3A0_1000, 0xE8BD_000C]);
+    let top = cpu.memory.len() as u32;
+    cpu.set_stack_pointer(top);
+    assert_eq!(cpu.registers[13], top);
+    assert_eq!(cpu.run_program(), Ok(StepResult::Halted));
+    assert_eq!(cpu.registers[2..4], [1, 2]);
+    assert_eq!(cpu.registers[13], top);
+    /* the stack pointer is set again on reset */
+    cpu.reset();
+    assert_eq!(cpu.registers[13], top);
+}
+
+#[test]
+fn pc_relative_loads_read_relative_to_pc_plus_eight() {
+    /* the ldr at 0x4 reads 0x4 + 8 + 4 = 0x10, skipping the word at 0xC */
+    let cpu = run("mov r1, #1\nldr r0, [pc, #4]\nb end\nmov r1, #2\nmov r1, #3\nend: halt");
+    assert_eq!(cpu.registers[0], 0xE3A0_1003);
+    assert_eq!(cpu.registers[1], 1);
+}
+
+#[test]
+fn literal_loads_read_their_pool_entry() {
+    let cpu = run("ldr r0, =0x12345678\nldr r1, =0xCAFEF00D\nhalt");
+    assert_eq!((cpu.registers[0], cpu.registers[1]), (0x1234_5678, 0xCAFE_F00D));
+}
+
+#[test]
+fn out_of_bounds_loads_stop_the_program() {
+    /* mov r1, #0x8000; ldr r0, [r1]; mov r2, #1 */
+    let mut cpu = load_words(&[0xE3A0_1902, 0xE591_0000, 0xE3A0_2001]);
+    assert_eq!(cpu.run_program(), Err(RuntimeError::OutOfBounds {addr : 0x8000}));
+    assert_eq!((cpu.registers[0], cpu.registers[2]), (0, 0));
+    assert_eq!(cpu.instruction_count(), 2);
+    assert_eq!(cpu.coverage(), vec![0x0, 0x4]);
+}
+
+#[test]
+fn writeback_to_pc_is_rejected() {
+    /* ldr r0, [pc, #4]! and ldr r0, [pc], #4 */
+    for word in [0xE5BF_0004, 0xE49F_0004].iter() {
+        let mut cpu = load_words(&[*word]);
+        assert_eq!(cpu.step(), Err(RuntimeError::InvalidEncoding {instruction : *word, reason : "Data Transfer instruction writes back to PC"}));
+    }
+}
+
+#[test]
+fn post_indexed_transfer_to_the_base_is_rejected() {
+    /* str r1, [r1], #4 */
+    let mut cpu = load_words(&[0xE481_1004]);
+    assert_eq!(cpu.step(), Err(RuntimeError::InvalidEncoding {instruction : 0xE481_1004, reason : "Data Transfer instruction writes back to Rd"}));
+}
+
+#[test]
+fn transfers_to_the_base_without_writeback_are_legal() {
+    let cpu = common::run("mov r1, #0x100\nstr r1, [r1]\nldr r1, [r1]\nldr r0, [pc, #-4]\nhalt");
+    assert_eq!(cpu.registers[1], 0x100);
+    assert_eq!(cpu.memory[0x100], 0);
+    assert_eq!(cpu.memory[0x101], 1);
+    /* the ldr at 0xC reads 0xC + 8 - 4, the halt */
+    assert_eq!(cpu.registers[0], emulate::HALT);
+}
+
+#[test]
+fn switching_endianness_byte_swaps_stored_words() {
+    let mut cpu = common::load("str r1, [r0]\nldr r2, [r0]\nstr r2, [r0, #4]\nhalt");
+    cpu.registers[0] = 0x100;
+    cpu.registers[1] = 0x1234_5678;
+    assert_eq!(cpu.endian(), Endian::Little);
+    assert_eq!(cpu.step(), Ok(StepResult::Continued));
+    cpu.set_endian(Endian::Big);
+    /* instructions are still fetched little endian */
+    assert_eq!(cpu.resume(), Ok(StepResult::Halted));
+    assert_eq!(cpu.registers[2], 0x7856_3412);
+    assert_eq!(cpu.memory[0x100..0x104], [0x78, 0x56, 0x34, 0x12]);
+    /* storing big endian puts the most significant byte first */
+    assert_eq!(cpu.memory[0x104..0x108], [0x78, 0x56, 0x34, 0x12]);
+}
+
+#[test]
+fn faulting_transfers_leave_the_base_unchanged() {
+    /* ldr r0, [r1, #4]!, str r0, [r1], #4 and ldr r0, [r1, #2]! */
+    for (word, base, error) in [
+        (0xE5B1_0004, 0x10_0000, RuntimeError::OutOfBounds {addr : 0x10_0004}),
+        (0xE481_0004, 0x10_0000, RuntimeError::OutOfBounds {addr : 0x10_0000}),
+        (0xE5B1_0002, 0x100, RuntimeError::UnalignedAccess {addr : 0x102})
+    ].iter() {
+        let mut cpu = load_words(&[*word]);
+        cpu.registers[1] = *base;
+        assert_eq!(cpu.step(), Err(error.clone()), "{:#010x}", word);
+        assert_eq!(cpu.registers[1], *base, "{:#010x}", word);
+    }
+}
+
+#[test]
+fn halfword_encoding_checks_run_before_the_base_changes() {
+    /* ldrh r1, [r1, #2]!, strsh r0, [r1, #2]! and ldrh r0, [r1, pc] */
+    for (word, error) in [
+        (0xE1F1_10B2, RuntimeError::InvalidEncoding {instruction : 0xE1F1_10B2, reason : "Halfword Data Transfer instruction writes back to Rd"}),
+        (0xE1E1_00F2, RuntimeError::InvalidInstruction {instruction : 0xE1E1_00F2, reason : "Halfword Data Transfer instruction stores a signed value"}),
+        (0xE191_00BF, RuntimeError::InvalidInstruction {instruction : 0xE191_00BF, reason : "Halfword Data Transfer instruction uses PC as Rm"})
+    ].iter() {
+        let mut cpu = load_words(&[*word]);
+        cpu.registers[1] = 0x100;
+        assert_eq!(cpu.step(), Err(error.clone()), "{:#010x}", word);
+        assert_eq!(cpu.registers[1], 0x100, "{:#010x}", word);
+        assert_eq!(cpu.validate().first().map(|warning| warning.addr), Some(0), "{:#010x}", word);
+    }
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use emulate::RuntimeError;
+use common::load_words;
+
+#[test]
+fn coprocessor_data_operations_are_unsupported() {
+    /* cdp p1, 0, c0, c0, c0 */
+    let mut cpu = load_words(&[0xEE00_0100]);
+    assert_eq!(cpu.step(), Err(RuntimeError::UnsupportedCoprocessor {cp_num : 1}));
+    assert_eq!(cpu.run_program(), Err(RuntimeError::UnsupportedCoprocessor {cp_num : 1}));
+}
+
+#[test]
+fn skipped_coprocessor_operations_continue() {
+    /* cdp p1, 0, c0, c0, c0; mov r0, #1 */
+    let mut cpu = load_words(&[0xEE00_0100, 0xE3A0_0001]);
+    cpu.set_skip_coprocessor(true);
+    assert_eq!(cpu.run_program(), Ok(emulate::StepResult::Halted));
+    assert_eq!(cpu.registers[0], 1);
+}
+
+#[test]
+fn undefined_instructions_are_unknown() {
+    let mut cpu = load_words(&[0xE7F0_00F0]);
+    assert_eq!(cpu.step(), Err(RuntimeError::UnknownInstruction {instruction : 0xE7F0_00F0}));
+    /* ldr r0, [r1, r2, lsl r3] is in the same space */
+    let mut cpu = load_words(&[0xE791_0312]);
+    assert_eq!(cpu.step(), Err(RuntimeError::UnknownInstruction {instruction : 0xE791_0312}));
+}